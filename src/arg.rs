@@ -5,4 +5,15 @@ use std::path::PathBuf;
 pub struct Args {
     #[clap(long, short, value_name = "path", parse(from_os_str))]
     pub config: PathBuf,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clap)]
+pub enum Command {
+    /// Imports the users of a TOML users file into the SQLite database of the config
+    ImportUsers {
+        #[clap(value_name = "path", parse(from_os_str))]
+        file: PathBuf,
+    },
 }
@@ -1,12 +1,93 @@
-use serde::Deserialize;
+use crate::modul::{ModulGruppe, ModulTyp};
+use chrono::{NaiveTime, Weekday};
+use serde::{de::Error, Deserialize, Deserializer};
 use serenity::prelude::TypeMapKey;
-use std::{path::PathBuf, sync::Arc};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[derive(Deserialize)]
 pub struct Config {
     pub discord: Discord,
     pub users: Users,
-    pub links: Links,
+    #[serde(rename = "kohorte")]
+    pub kohorten: Vec<Kohorte>,
+    /// Automatic refresh of the schedule, disabled if not set
+    pub refresh: Option<Refresh>,
+    /// Weekly preview in the group channels, disabled if not set
+    pub preview: Option<Preview>,
+    #[serde(default)]
+    pub reminders: Reminders,
+    /// Directory containing the config file
+    #[serde(skip)]
+    pub directory: PathBuf,
+}
+
+impl Config {
+    /// Resolves a path relative to the directory of the config file
+    pub fn resolve_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            path.to_owned()
+        } else {
+            self.directory.join(path)
+        }
+    }
+
+    pub fn kohorte(&self, id: &str) -> Option<&Kohorte> {
+        self.kohorten.iter().find(|kohorte| kohorte.id == id)
+    }
+
+    /// Returns the configuration of a module
+    pub fn modul(&self, typ: &ModulTyp) -> Option<&Modul> {
+        self.kohorte(&typ.kohorte)?
+            .module
+            .iter()
+            .find(|modul| modul.id == typ.id)
+    }
+
+    /// Returns the channels of a date, which are all groups of its cohort if it has no group
+    pub fn groups_for(&self, typ: &ModulTyp, gruppe: &Option<ModulGruppe>) -> Vec<&Group> {
+        match self.kohorte(&typ.kohorte) {
+            Some(kohorte) => kohorte.groups_for(gruppe),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the path of the schedule snapshot
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.resolve_path(&self.users.file)
+            .with_file_name(crate::snapshot::FILE_NAME)
+    }
+
+    /// Returns the reminder settings for the dates of a module in the channel of a group
+    ///
+    /// Settings of the module take precedence over those of the group, which take precedence over
+    /// the `reminders` section.
+    pub fn channel_settings(&self, typ: &ModulTyp, group: &Group) -> ChannelSettings {
+        let layers = self
+            .modul(typ)
+            .map(|modul| &modul.reminders)
+            .into_iter()
+            .chain(vec![&group.reminders, &self.reminders.channel])
+            .collect::<Vec<_>>();
+        ChannelSettings {
+            send_before: layers
+                .iter()
+                .find_map(|v| v.send_before.clone())
+                .unwrap_or_else(|| vec![30]),
+            mention: layers.iter().find_map(|v| v.mention).unwrap_or_default(),
+            mention_first_only: layers
+                .iter()
+                .find_map(|v| v.mention_first_only)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns the path of the log of delivered reminders
+    pub fn delivery_log_path(&self) -> PathBuf {
+        self.resolve_path(&self.users.file)
+            .with_file_name(crate::zustellung::FILE_NAME)
+    }
 }
 
 impl TypeMapKey for Config {
@@ -17,45 +98,451 @@ impl TypeMapKey for Config {
 pub struct Discord {
     pub bot_token: String,
     pub guild_id: u64,
-    pub gruppe_1: Group,
-    pub gruppe_2: Group,
-    pub gruppe_3: Group,
-    pub gruppe_4: Group,
+    /// Whether a `fällt aus` notice is sent instead of the reminder of a cancelled date
+    #[serde(default)]
+    pub notify_ausfall: bool,
+    /// Whether `dm set group` also assigns the role of the group and removes the roles of the
+    /// other groups of the cohort
+    #[serde(default)]
+    pub assign_roles: bool,
+}
+
+/// A study program and semester (e.g. AIN1) with its own modules, groups and channels
+#[derive(Deserialize)]
+pub struct Kohorte {
+    /// Unique identifier of the cohort (e.g. `ain1`)
+    pub id: String,
+    /// Name of the cohort that is displayed in messages
+    pub name: String,
+    /// Role of all members of the cohort
+    pub role_id: Option<u64>,
+    #[serde(default, rename = "gruppe")]
+    pub groups: Vec<Group>,
+    #[serde(rename = "modul")]
+    pub module: Vec<Modul>,
+}
+
+impl Kohorte {
+    pub fn group(&self, gruppe: &ModulGruppe) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name == gruppe.0)
+    }
+
+    /// Returns the channels of a date, which are all groups if the date has no group
+    pub fn groups_for(&self, gruppe: &Option<ModulGruppe>) -> Vec<&Group> {
+        match gruppe {
+            Some(gruppe) => self.group(gruppe).into_iter().collect(),
+            None => self.groups.iter().collect(),
+        }
+    }
+
+    /// Returns the group of the first role for which `has_role` returns `true`
+    pub fn find_group<F: Fn(u64) -> bool>(&self, has_role: F) -> Option<ModulGruppe> {
+        self.groups
+            .iter()
+            .find(|group| has_role(group.role_id))
+            .map(Group::gruppe)
+    }
+
+    /// Returns whether `has_role` returns `true` for the role of the cohort or of one of its groups
+    pub fn is_member<F: Fn(u64) -> bool>(&self, has_role: F) -> bool {
+        self.role_id.is_some_and(&has_role)
+            || self.groups.iter().any(|group| has_role(group.role_id))
+    }
+
+    /// Parses the value of `dm set group`, which is either the name or the short name of a group
+    pub fn parse_group(&self, input: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| {
+            group.name.eq_ignore_ascii_case(input) || group.short_name().eq_ignore_ascii_case(input)
+        })
+    }
 }
 
 #[derive(Deserialize)]
 pub struct Group {
+    /// Name of the group in LSF (e.g. `Gruppe 1`)
+    pub name: String,
     pub channel_id: u64,
     pub role_id: u64,
+    #[serde(flatten)]
+    pub reminders: ChannelReminders,
+}
+
+impl Group {
+    pub fn gruppe(&self) -> ModulGruppe {
+        ModulGruppe::new(&self.name)
+    }
+
+    /// Returns the last word of the name (e.g. `1` for `Gruppe 1`)
+    pub fn short_name(&self) -> &str {
+        self.name.split_whitespace().last().unwrap_or(&self.name)
+    }
+}
+
+/// Settings of the reminders
+#[derive(Default, Deserialize)]
+pub struct Reminders {
+    /// Handling of reminders that were missed while the bot was offline
+    #[serde(default)]
+    pub catch_up: CatchUp,
+    /// Handling of the reminders in the group channels after their date has ended
+    #[serde(default)]
+    pub cleanup: Cleanup,
+    /// Defaults of the reminders in all group channels
+    #[serde(flatten)]
+    pub channel: ChannelReminders,
+}
+
+/// Settings of the reminders in the group channels, which can be set in the `reminders` section
+/// and overridden per group and per module
+#[derive(Default, Deserialize)]
+pub struct ChannelReminders {
+    /// Minutes before the beginning of a date at which a reminder is posted, defaults to `[30]`
+    pub send_before: Option<Vec<u64>>,
+    /// Who is mentioned in the reminders, defaults to the role of the group
+    pub mention: Option<Mention>,
+    /// Whether only the reminders of the first date of a day mention anyone
+    pub mention_first_only: Option<bool>,
+}
+
+/// Reminder settings of a channel with the defaults applied
+#[derive(Debug, Eq, PartialEq)]
+pub struct ChannelSettings {
+    pub send_before: Vec<u64>,
+    pub mention: Mention,
+    pub mention_first_only: bool,
+}
+
+/// Who is mentioned in a channel message, ordered from the widest to the narrowest mention
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mention {
+    /// Mentions the role of the group
+    #[default]
+    Role,
+    /// Mentions everyone that is online with `@here`
+    Here,
+    None,
+}
+
+impl Mention {
+    /// Returns the text that mentions the members of a group, if any
+    pub fn to_content(self, group: &Group) -> Option<String> {
+        match self {
+            Mention::Role => Some(format!("<@&{}>", group.role_id)),
+            Mention::Here => Some("@here".to_owned()),
+            Mention::None => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CatchUp {
+    /// Sends missed reminders late with a note, as long as their date has not begun
+    #[default]
+    Late,
+    /// Skips missed reminders
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cleanup {
+    /// Keeps the reminders unchanged
+    #[default]
+    Keep,
+    /// Deletes the reminders after the end of their date
+    Delete,
+    /// Marks the reminders as running during their date and as ended afterwards
+    Edit,
 }
 
 #[derive(Deserialize)]
 pub struct Users {
+    #[serde(default)]
+    pub backend: Backend,
+    /// Path of the TOML file or the SQLite database
     pub file: PathBuf,
+    /// Number of previous versions of the users file to keep
+    #[serde(default = "Users::default_backups")]
+    pub backups: usize,
+}
+
+impl Users {
+    fn default_backups() -> usize {
+        3
+    }
+}
+
+/// Storage backend for the settings of the users
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    File,
+    Sqlite,
 }
 
 #[derive(Deserialize)]
-pub struct Links {
-    pub mathematik1: LinkData,
-    pub programmiertechnik1: LinkData,
-    pub softwaremodellierung: LinkData,
-    pub digitaltechnik: LinkData,
+pub struct Refresh {
+    /// Minutes between two refreshes
+    #[serde(deserialize_with = "deserialize_minutes")]
+    pub interval: u64,
+    /// Local time (`HH:MM`) of an additional nightly refresh
+    #[serde(default, deserialize_with = "deserialize_time")]
+    pub nightly: Option<NaiveTime>,
+    /// Minutes to wait after the first failed refresh, doubled after every further failure
+    #[serde(
+        default = "Refresh::default_retry",
+        deserialize_with = "deserialize_minutes"
+    )]
+    pub retry: u64,
+    /// Maximum number of minutes to wait after failed refreshes, defaults to `interval`
+    #[serde(default, deserialize_with = "deserialize_max_retry")]
+    pub max_retry: Option<u64>,
 }
 
-impl Links {
-    pub fn to_vec(&self) -> Vec<&LinkData> {
-        vec![
-            &self.mathematik1,
-            &self.programmiertechnik1,
-            &self.softwaremodellierung,
-            &self.digitaltechnik,
-        ]
+impl Refresh {
+    fn default_retry() -> u64 {
+        5
     }
 }
 
 #[derive(Deserialize)]
-pub struct LinkData {
-    pub lsf: String,
+pub struct Preview {
+    /// Weekday of the post (e.g. `Sun`)
+    #[serde(default = "Preview::default_weekday")]
+    pub weekday: Weekday,
+    /// Local time (`HH:MM`) of the post, defaults to `18:00`
+    #[serde(default, deserialize_with = "deserialize_time")]
+    pub time: Option<NaiveTime>,
+}
+
+impl Preview {
+    fn default_weekday() -> Weekday {
+        Weekday::Sun
+    }
+
+    pub fn time(&self) -> NaiveTime {
+        self.time.unwrap_or_else(|| NaiveTime::from_hms(18, 0, 0))
+    }
+}
+
+fn deserialize_minutes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match u64::deserialize(deserializer)? {
+        0 => Err(D::Error::custom("expected at least one minute")),
+        v => Ok(v),
+    }
+}
+
+fn deserialize_max_retry<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    deserialize_minutes(deserializer).map(Some)
+}
+
+fn deserialize_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveTime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|v| NaiveTime::parse_from_str(&v, "%H:%M").map_err(D::Error::custom))
+        .transpose()
+}
+
+/// A module of the schedule
+#[derive(Deserialize)]
+pub struct Modul {
+    /// Unique identifier of the module
+    pub id: String,
+    /// Name of the module that is displayed in messages
+    pub name: String,
+    /// Title of the LSF Einzelansicht page without the ` - Einzelansicht` suffix, defaults to
+    /// `name`
+    pub lsf_title: Option<String>,
+    /// Color of the embeds as hex value (e.g. `#1f8b4c`)
+    #[serde(default, deserialize_with = "deserialize_color")]
+    pub color: Option<u32>,
     pub vorlesungen: Option<String>,
     pub uebungen: Option<String>,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub reminders: ChannelReminders,
+}
+
+impl Modul {
+    pub fn typ(&self, kohorte: &str) -> ModulTyp {
+        ModulTyp::new(kohorte, &self.id, &self.name)
+    }
+
+    pub fn lsf_title(&self) -> &str {
+        self.lsf_title.as_deref().unwrap_or(&self.name)
+    }
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|v| u32::from_str_radix(v.trim_start_matches('#'), 16).map_err(D::Error::custom))
+        .transpose()
+}
+
+/// Where the schedule of a module is fetched from
+///
+/// Exactly one of the keys `lsf`, `ical` or `file` has to be set for every module.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// URL of the LSF Einzelansicht page
+    Lsf(String),
+    /// URL or path of an iCalendar file
+    Ical(String),
+    /// Path of a TOML or JSON schedule file
+    File(PathBuf),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        toml::from_str(
+            r##"
+            [discord]
+            bot_token = "token"
+            guild_id = 1
+
+            [users]
+            file = "users.toml"
+
+            [reminders]
+            catch_up = "skip"
+            cleanup = "edit"
+            send_before = [15]
+            mention_first_only = true
+
+            [[kohorte]]
+            id = "ain1"
+            name = "AIN 1"
+            role_id = 20
+            gruppe = [
+                { name = "Gruppe 1", channel_id = 11, role_id = 21 },
+                { name = "Gruppe A", channel_id = 12, role_id = 22, mention = "here" },
+            ]
+
+            [[kohorte.modul]]
+            id = "digitaltechnik"
+            name = "Digitaltechnik"
+            lsf_title = "AIN1 Digitaltechnik"
+            color = "#1f8b4c"
+            lsf = "https://lsf.example.org"
+            send_before = [60, 10]
+
+            [[kohorte.modul]]
+            id = "mathematik1"
+            name = "Mathematik 1"
+            file = "mathematik1.toml"
+
+            [[kohorte]]
+            id = "win1"
+            name = "WIN 1"
+
+            [[kohorte.modul]]
+            id = "mathematik1"
+            name = "Wirtschaftsmathematik"
+            file = "win1_mathematik1.toml"
+            "##,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn refresh() {
+        let refresh = toml::from_str::<Refresh>("interval = 30").unwrap();
+        assert_eq!((refresh.interval, refresh.retry), (30, 5));
+        assert_eq!(refresh.max_retry, None);
+        for invalid in &[
+            "interval = 0",
+            "interval = 30\nretry = 0",
+            "interval = 30\nmax_retry = 0",
+        ] {
+            assert!(toml::from_str::<Refresh>(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn modul() {
+        let config = config();
+        let typ = ModulTyp::new("ain1", "digitaltechnik", "Digitaltechnik");
+        let modul = config.modul(&typ).unwrap();
+        assert_eq!(modul.color, Some(0x1f8b4c));
+        assert_eq!(modul.lsf_title(), "AIN1 Digitaltechnik");
+        assert!(matches!(modul.source, Source::Lsf(_)));
+        let module = &config.kohorten[0].module;
+        assert_eq!(module[1].lsf_title(), "Mathematik 1");
+        assert!(matches!(module[1].source, Source::File(_)));
+    }
+
+    #[test]
+    fn channel_settings() {
+        let config = config();
+        assert_eq!(config.reminders.catch_up, CatchUp::Skip);
+        assert_eq!(config.reminders.cleanup, Cleanup::Edit);
+        let kohorte = config.kohorte("ain1").unwrap();
+        let digitaltechnik = ModulTyp::new("ain1", "digitaltechnik", "Digitaltechnik");
+        let mathematik = ModulTyp::new("ain1", "mathematik1", "Mathematik 1");
+        assert_eq!(
+            config.channel_settings(&digitaltechnik, &kohorte.groups[0]),
+            ChannelSettings {
+                send_before: vec![60, 10],
+                mention: Mention::Role,
+                mention_first_only: true,
+            }
+        );
+        assert_eq!(
+            config.channel_settings(&mathematik, &kohorte.groups[1]),
+            ChannelSettings {
+                send_before: vec![15],
+                mention: Mention::Here,
+                mention_first_only: true,
+            }
+        );
+        // Combined messages use the widest mention
+        assert_eq!(
+            vec![Mention::None, Mention::Here, Mention::Role]
+                .into_iter()
+                .min(),
+            Some(Mention::Role)
+        );
+    }
+
+    #[test]
+    fn modul_of_kohorte() {
+        let config = config();
+        let typ = ModulTyp::new("win1", "mathematik1", "Wirtschaftsmathematik");
+        assert_eq!(config.modul(&typ).unwrap().name, "Wirtschaftsmathematik");
+        assert!(config.groups_for(&typ, &None).is_empty());
+    }
+
+    #[test]
+    fn groups() {
+        let config = config();
+        let kohorte = config.kohorte("ain1").unwrap();
+        assert_eq!(kohorte.parse_group("a").unwrap().channel_id, 12);
+        assert_eq!(kohorte.parse_group("Gruppe 1").unwrap().channel_id, 11);
+        assert!(kohorte.parse_group("2").is_none());
+        assert_eq!(kohorte.groups_for(&None).len(), 2);
+        assert_eq!(
+            kohorte.groups_for(&Some(ModulGruppe::new("Gruppe A")))[0].role_id,
+            22
+        );
+        assert!(kohorte
+            .groups_for(&Some(ModulGruppe::new("Gruppe 2")))
+            .is_empty());
+        assert_eq!(
+            kohorte.find_group(|role_id| role_id == 22),
+            Some(ModulGruppe::new("Gruppe A"))
+        );
+        assert!(kohorte.is_member(|role_id| role_id == 20));
+        assert!(!config.kohorte("win1").unwrap().is_member(|_| true));
+    }
 }
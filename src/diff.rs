@@ -0,0 +1,278 @@
+use crate::locale::{Locale, Text};
+use crate::modul::{MessageData, Modul};
+use crate::user::{self, Mitgliedschaft};
+use chrono::{DateTime, Datelike, Utc};
+use serenity::{builder::CreateEmbed, utils::Color};
+
+/// A change of a single date between two versions of the schedule
+pub enum Change<'m> {
+    Added(MessageData<'m>),
+    Removed(MessageData<'m>),
+    Changed {
+        old: MessageData<'m>,
+        new: MessageData<'m>,
+    },
+}
+
+impl Change<'_> {
+    fn current(&self) -> &MessageData<'_> {
+        match self {
+            Change::Added(v) | Change::Removed(v) => v,
+            Change::Changed { new, .. } => new,
+        }
+    }
+
+    pub fn modul(&self) -> &Modul {
+        self.current().modul
+    }
+
+    /// Returns whether the change is relevant for members of the given cohorts
+    pub fn affects(&self, kohorten: &[Mitgliedschaft]) -> bool {
+        user::affects(kohorten, self.current().modul)
+    }
+
+    /// Returns whether the date has not ended yet
+    pub fn is_upcoming(&self, now: DateTime<Utc>) -> bool {
+        match self {
+            Change::Added(v) | Change::Removed(v) => v.modul_termin.ende > now,
+            Change::Changed { old, new } => {
+                old.modul_termin.ende > now || new.modul_termin.ende > now
+            }
+        }
+    }
+
+    fn add_field<'a>(&self, locale: &Locale, embed: &'a mut CreateEmbed) -> &'a mut CreateEmbed {
+        let title = self.current().modul.title();
+        let value = match self {
+            Change::Added(v) => {
+                format!(
+                    "{}: {}",
+                    locale.text(Text::NeuerTermin),
+                    describe(v, locale)
+                )
+            }
+            Change::Removed(v) => {
+                format!("{}: {}", locale.text(Text::Entfaellt), describe(v, locale))
+            }
+            Change::Changed { old, new } => format!(
+                "{}: {}\n{}: {}",
+                locale.text(Text::Vorher),
+                describe(old, locale),
+                locale.text(Text::Jetzt),
+                describe(new, locale)
+            ),
+        };
+        embed.field(title, value, false)
+    }
+}
+
+/// Fills an embed announcing the given changes
+///
+/// Discord allows at most 25 fields per embed, so callers have to split longer lists.
+pub fn to_embed<'a>(
+    changes: &[&Change<'_>],
+    locale: &Locale,
+    embed: &'a mut CreateEmbed,
+) -> &'a mut CreateEmbed {
+    embed
+        .title(locale.text(Text::Stundenplanaenderung))
+        .color(Color::RED);
+    for change in changes {
+        change.add_field(locale, embed);
+    }
+    embed
+}
+
+fn describe(message: &MessageData<'_>, locale: &Locale) -> String {
+    let termin = message.modul_termin;
+    let beginn = locale.local(&termin.beginn);
+    let mut string = format!(
+        "{} {} {} - {}",
+        locale.wochentag(beginn.weekday()),
+        locale.format_date(beginn.naive_local().date()),
+        beginn.format("%H:%M"),
+        locale.local(&termin.ende).format("%H:%M")
+    );
+    if let Some(raum) = &message.modul.raum {
+        string.push_str(&format!(", {} {}", locale.text(Text::Raum), raum));
+    }
+    if termin.ausfall {
+        string.push_str(&format!(
+            ", {}",
+            locale.text(Text::FaelltAus).to_lowercase()
+        ));
+    }
+    if let Some(bemerkung) = message.modul.bemerkung.as_ref().filter(|v| !v.is_empty()) {
+        string.push_str(&format!(" ({})", bemerkung));
+    }
+    string
+}
+
+fn entries(module: &[Modul]) -> Vec<MessageData<'_>> {
+    module
+        .iter()
+        .flat_map(|modul| modul.messages(|_| true))
+        .collect()
+}
+
+fn same_modul(a: &MessageData<'_>, b: &MessageData<'_>) -> bool {
+    a.modul.typ == b.modul.typ && a.modul.gruppe == b.modul.gruppe
+}
+
+fn same_details(a: &MessageData<'_>, b: &MessageData<'_>) -> bool {
+    a.modul_termin == b.modul_termin
+        && a.modul.raum == b.modul.raum
+        && a.modul.bemerkung == b.modul.bemerkung
+}
+
+/// Pairs up the remaining entries of both versions that satisfy `matcher`
+fn match_entries<'m, F>(
+    old: &mut [Option<MessageData<'m>>],
+    new: &mut [Option<MessageData<'m>>],
+    changes: &mut Vec<Change<'m>>,
+    matcher: F,
+) where
+    F: Fn(&MessageData<'_>, &MessageData<'_>) -> bool,
+{
+    for old_entry in old.iter_mut() {
+        let position = match old_entry {
+            Some(o) => new
+                .iter()
+                .position(|n| n.as_ref().is_some_and(|n| matcher(o, n))),
+            None => continue,
+        };
+        if let Some(i) = position {
+            let (o, n) = (old_entry.take().unwrap(), new[i].take().unwrap());
+            if !same_details(&o, &n) {
+                changes.push(Change::Changed { old: o, new: n });
+            }
+        }
+    }
+}
+
+/// Compares two versions of the schedule
+///
+/// Dates of the same module and group are matched by their time first and by their day second,
+/// so a shifted time or a moved room shows up as a single [`Change::Changed`].
+pub fn diff<'m>(old: &'m [Modul], new: &'m [Modul]) -> Vec<Change<'m>> {
+    let mut old = entries(old).into_iter().map(Some).collect::<Vec<_>>();
+    let mut new = entries(new).into_iter().map(Some).collect::<Vec<_>>();
+    let mut changes = Vec::new();
+
+    match_entries(&mut old, &mut new, &mut changes, |a, b| {
+        same_modul(a, b) && a.modul_termin == b.modul_termin
+    });
+    match_entries(&mut old, &mut new, &mut changes, |a, b| {
+        same_modul(a, b) && a.modul_termin.beginn.date() == b.modul_termin.beginn.date()
+    });
+
+    changes.extend(old.into_iter().flatten().map(Change::Removed));
+    changes.extend(new.into_iter().flatten().map(Change::Added));
+    changes.sort_by_key(|change| change.current().modul_termin.beginn);
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, termin};
+    use crate::modul::{ModulGruppe, ModulTermin};
+
+    fn modul(raum: &str, termine: Vec<ModulTermin>) -> Modul {
+        Modul {
+            raum: Some(raum.to_owned()),
+            ..fixture::modul("mathematik1", Some("Gruppe 1"), termine)
+        }
+    }
+
+    #[test]
+    fn unchanged() {
+        let old = vec![modul(
+            "F-033",
+            vec![termin(5, (8, 0), (10, 0)), termin(12, (8, 0), (10, 0))],
+        )];
+        let new = old.clone();
+        assert!(diff(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn added_and_removed() {
+        let old = vec![modul(
+            "F-033",
+            vec![termin(5, (8, 0), (10, 0)), termin(12, (8, 0), (10, 0))],
+        )];
+        let new = vec![modul(
+            "F-033",
+            vec![termin(12, (8, 0), (10, 0)), termin(19, (8, 0), (10, 0))],
+        )];
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(
+            &changes[0],
+            Change::Removed(v) if *v.modul_termin == termin(5, (8, 0), (10, 0))
+        ));
+        assert!(matches!(
+            &changes[1],
+            Change::Added(v) if *v.modul_termin == termin(19, (8, 0), (10, 0))
+        ));
+    }
+
+    #[test]
+    fn shifted_time() {
+        let old = vec![modul("F-033", vec![termin(5, (8, 0), (10, 0))])];
+        let new = vec![modul("F-033", vec![termin(5, (10, 0), (12, 0))])];
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(
+            &changes[0],
+            Change::Changed { old, new }
+                if *old.modul_termin == termin(5, (8, 0), (10, 0))
+                    && *new.modul_termin == termin(5, (10, 0), (12, 0))
+        ));
+    }
+
+    #[test]
+    fn moved_room() {
+        let old = vec![modul(
+            "F-033",
+            vec![termin(5, (8, 0), (10, 0)), termin(12, (8, 0), (10, 0))],
+        )];
+        let new = vec![
+            modul("F-033", vec![termin(5, (8, 0), (10, 0))]),
+            modul("O-001", vec![termin(12, (8, 0), (10, 0))]),
+        ];
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(
+            &changes[0],
+            Change::Changed { new, .. } if new.modul.raum.as_deref() == Some("O-001")
+        ));
+    }
+
+    #[test]
+    fn cancelled() {
+        let old = vec![modul("F-033", vec![termin(5, (8, 0), (10, 0))])];
+        let mut new = old.clone();
+        new[0].termine[0].ausfall = true;
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(&changes[0], Change::Changed { new, .. } if new.modul_termin.ausfall));
+    }
+
+    #[test]
+    fn affects() {
+        let old = Vec::new();
+        let new = vec![modul("F-033", vec![termin(5, (8, 0), (10, 0))])];
+        let changes = diff(&old, &new);
+        let mitgliedschaft = |kohorte: &str, gruppe: Option<&str>| Mitgliedschaft {
+            kohorte: kohorte.to_owned(),
+            gruppe: gruppe.map(ModulGruppe::new),
+            manuell: false,
+        };
+        assert!(changes[0].affects(&[mitgliedschaft("ain1", Some("Gruppe 1"))]));
+        assert!(!changes[0].affects(&[mitgliedschaft("ain1", Some("Gruppe 2"))]));
+        assert!(!changes[0].affects(&[mitgliedschaft("ain1", None)]));
+        assert!(!changes[0].affects(&[mitgliedschaft("win1", Some("Gruppe 1"))]));
+        assert!(!changes[0].affects(&[]));
+    }
+}
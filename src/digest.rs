@@ -0,0 +1,106 @@
+use crate::config::Config;
+use crate::locale::{Locale, Text};
+use crate::modul::MessageData;
+use chrono::{Date, DateTime, Datelike, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serenity::{builder::CreateEmbed, utils::Color};
+
+/// Returns the first time after `after` at which the digest at the `time` in the time zone `tz`
+/// is due, days on which the time does not exist are skipped
+pub fn next(time: NaiveTime, tz: Tz, after: DateTime<Utc>) -> DateTime<Utc> {
+    next_on(time, tz, after, |_| true)
+}
+
+/// Returns the first time after `after` at the `time` in the time zone `tz` on a day for which
+/// `filter` returns `true`
+pub fn next_on<F: Fn(&Date<Tz>) -> bool>(
+    time: NaiveTime,
+    tz: Tz,
+    after: DateTime<Utc>,
+    filter: F,
+) -> DateTime<Utc> {
+    let mut date = after.with_timezone(&tz).date();
+    loop {
+        if let Some(due) = date
+            .and_time(time)
+            .map(|v| v.with_timezone(&Utc))
+            .filter(|v| *v > after && filter(&date))
+        {
+            return due;
+        }
+        date = date.succ();
+    }
+}
+
+/// Fills an embed listing the dates of a single day
+///
+/// Discord allows at most 25 fields per embed, so callers have to split longer lists.
+pub fn to_embed<'a>(
+    messages: &[MessageData<'_>],
+    cfg: &Config,
+    locale: &Locale,
+    embed: &'a mut CreateEmbed,
+) -> &'a mut CreateEmbed {
+    if let Some(message) = messages.first() {
+        let beginn = locale.local(&message.modul_termin.beginn);
+        embed.title(locale.text(Text::Stundenplan {
+            wochentag: locale.wochentag(beginn.weekday()),
+            date: &locale.format_date(beginn.naive_local().date()),
+        }));
+    }
+    embed.color(Color::BLUE);
+    for message in messages {
+        embed.field(message.modul.title(), describe(message, cfg, locale), false);
+    }
+    embed
+}
+
+/// Describes a date in a single field, which is also used for combined reminders
+pub fn describe(message: &MessageData<'_>, cfg: &Config, locale: &Locale) -> String {
+    let termin = message.modul_termin;
+    let time = format!(
+        "{} - {}",
+        locale.local(&termin.beginn).format("%H:%M"),
+        locale.local(&termin.ende).format("%H:%M")
+    );
+    let mut string = if termin.ausfall {
+        format!(
+            "~~{}~~ ({})",
+            time,
+            locale.text(Text::FaelltAus).to_lowercase()
+        )
+    } else {
+        time
+    };
+    if let Some(raum) = &message.modul.raum {
+        string.push_str(&format!(", {} {}", locale.text(Text::Raum), raum));
+    }
+    if let Some(online_link) = message.modul.online_link(cfg) {
+        string.push_str(&format!("\n{}", online_link));
+    }
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn next_time() {
+        let time = NaiveTime::from_hms(7, 0, 0);
+        let berlin = |day, hour, minute| {
+            chrono_tz::Europe::Berlin
+                .ymd(2020, 10, day)
+                .and_hms(hour, minute, 0)
+                .with_timezone(&Utc)
+        };
+        let tz = chrono_tz::Europe::Berlin;
+        assert_eq!(next(time, tz, berlin(5, 6, 59)), berlin(5, 7, 0));
+        assert_eq!(next(time, tz, berlin(5, 7, 0)), berlin(6, 7, 0));
+        assert_eq!(
+            next(time, chrono_tz::Europe::London, berlin(5, 7, 0)),
+            berlin(5, 8, 0)
+        );
+    }
+}
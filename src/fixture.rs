@@ -0,0 +1,39 @@
+//! Modules, dates and directories shared by the tests
+
+use crate::modul::{Modul, ModulGruppe, ModulTermin, ModulTyp};
+use chrono::TimeZone;
+use std::fs;
+use std::path::PathBuf;
+
+/// Date from `beginn` to `ende` (hour and minute) on a day of October 2020 in Berlin
+pub fn termin(day: u32, beginn: (u32, u32), ende: (u32, u32)) -> ModulTermin {
+    let date = chrono_tz::Europe::Berlin.ymd(2020, 10, day);
+    ModulTermin {
+        beginn: date.and_hms(beginn.0, beginn.1, 0),
+        ende: date.and_hms(ende.0, ende.1, 0),
+        ausfall: false,
+    }
+}
+
+/// Module of the cohort `ain1` without any optional details
+pub fn modul(id: &str, gruppe: Option<&str>, termine: Vec<ModulTermin>) -> Modul {
+    Modul {
+        typ: ModulTyp::new("ain1", id, id),
+        gruppe: gruppe.map(ModulGruppe::new),
+        termine,
+        raum: None,
+        bemerkung: None,
+        art: None,
+        dozent: None,
+        rhythmus: None,
+        dauer: None,
+    }
+}
+
+/// Creates an empty directory for the files of a test
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lsfbot-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
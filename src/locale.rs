@@ -0,0 +1,416 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Weekday};
+use chrono_humanize::{Accuracy, HumanTime, Tense};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Language of the texts sent to a user
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sprache {
+    #[default]
+    De,
+    En,
+}
+
+impl FromStr for Sprache {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "de" => Ok(Sprache::De),
+            "en" => Ok(Sprache::En),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Texts that are rendered in the language of the recipient
+pub enum Text<'a> {
+    Error(&'a str),
+    InvalidDateFormat,
+    KeineVeranstaltungenAm(&'a str),
+    KeineVeranstaltungen,
+    Enabled,
+    Disabled,
+    Removed,
+    Paused(&'a str),
+    Resumed,
+    Set {
+        option: &'a str,
+        value: &'a str,
+    },
+    SetGroup {
+        kohorte: &'a str,
+        value: &'a str,
+    },
+    UnknownValue {
+        value: &'a str,
+        available: &'a str,
+    },
+    UnknownCohort {
+        value: &'a str,
+        available: &'a str,
+    },
+    MissingCohort {
+        available: &'a str,
+    },
+    UnknownModule {
+        value: &'a str,
+        available: &'a str,
+    },
+    UnknownSubcommand(&'a str),
+    UserNotFound,
+    MissingManageRoles,
+    RoleAboveBot(&'a str),
+    Updated,
+    Configuration,
+    Status,
+    LastSuccess,
+    LastFailure,
+    NextRun,
+    Online,
+    Art,
+    Dozent,
+    Raum,
+    Rhythmus,
+    Dauer,
+    Bemerkung,
+    FaelltAus,
+    Stundenplanaenderung,
+    NeuerTermin,
+    Entfaellt,
+    Vorher,
+    Jetzt,
+    Stundenplan {
+        wochentag: &'a str,
+        date: &'a str,
+    },
+    TermineUm(&'a str),
+    AbweichenderRhythmus(&'a str),
+    Verspaetet,
+    LaeuftGerade,
+    Beendet,
+    /// Label of an option of `dm set` in `dm get`
+    Einstellung(&'a str),
+    An,
+    Aus,
+    Keine,
+    Standard,
+    Bis(&'a str),
+    Abo {
+        vorlesungen: &'a str,
+        uebungen: &'a str,
+    },
+}
+
+/// Language and time zone in which texts and times are rendered
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Locale {
+    pub sprache: Sprache,
+    pub zeitzone: Tz,
+}
+
+impl Default for Locale {
+    /// German and `Europe/Berlin`, which is used for the group channels
+    fn default() -> Self {
+        Self {
+            sprache: Sprache::De,
+            zeitzone: chrono_tz::Europe::Berlin,
+        }
+    }
+}
+
+impl Locale {
+    pub fn text(&self, text: Text<'_>) -> String {
+        use Sprache::*;
+        match (self.sprache, text) {
+            (De, Text::Error(e)) => format!("Fehler: {}", e),
+            (En, Text::Error(e)) => format!("Error: {}", e),
+            (De, Text::InvalidDateFormat) => "Fehler: Ungültiges Datumsformat".to_owned(),
+            (En, Text::InvalidDateFormat) => "Error: Invalid date format".to_owned(),
+            (De, Text::KeineVeranstaltungenAm(date)) => {
+                format!("Keine Lehrveranstaltungen am {}", date)
+            }
+            (En, Text::KeineVeranstaltungenAm(date)) => format!("No lectures on {}", date),
+            (De, Text::Enabled) => "Direktnachrichten aktiviert".to_owned(),
+            (En, Text::Enabled) => "Enabled direct messages".to_owned(),
+            (De, Text::Disabled) => "Direktnachrichten deaktiviert".to_owned(),
+            (En, Text::Disabled) => "Disabled direct messages".to_owned(),
+            (De, Text::Removed) => {
+                "Direktnachrichten deaktiviert und Konfiguration gelöscht".to_owned()
+            }
+            (En, Text::Removed) => "Disabled direct messages and removed configuration".to_owned(),
+            (De, Text::Paused(date)) => format!("Direktnachrichten pausiert bis {}", date),
+            (En, Text::Paused(date)) => format!("Paused direct messages until {}", date),
+            (De, Text::Resumed) => "Direktnachrichten fortgesetzt".to_owned(),
+            (En, Text::Resumed) => "Resumed direct messages".to_owned(),
+            (De, Text::Set { option, value }) => format!("`{}` auf `{}` gesetzt", option, value),
+            (En, Text::Set { option, value }) => format!("Set `{}` to `{}`", option, value),
+            (De, Text::SetGroup { kohorte, value }) => {
+                format!("`group` von `{}` auf `{}` gesetzt", kohorte, value)
+            }
+            (En, Text::SetGroup { kohorte, value }) => {
+                format!("Set `group` of `{}` to `{}`", kohorte, value)
+            }
+            (De, Text::UnknownValue { value, available }) => format!(
+                "Fehler: Unbekannter Wert `{}` (mögliche Werte: {})",
+                value, available
+            ),
+            (En, Text::UnknownValue { value, available }) => format!(
+                "Error: Unknown value `{}` (available values: {})",
+                value, available
+            ),
+            (De, Text::UnknownCohort { value, available }) => format!(
+                "Fehler: Unbekannte Kohorte `{}` (mögliche Werte: {})",
+                value, available
+            ),
+            (En, Text::UnknownCohort { value, available }) => format!(
+                "Error: Unknown cohort `{}` (available values: {})",
+                value, available
+            ),
+            (De, Text::MissingCohort { available }) => {
+                format!("Fehler: Fehlende Kohorte (mögliche Werte: {})", available)
+            }
+            (En, Text::MissingCohort { available }) => {
+                format!("Error: Missing cohort (available values: {})", available)
+            }
+            (De, Text::UnknownModule { value, available }) => format!(
+                "Fehler: Unbekanntes Modul `{}` (mögliche Werte: {})",
+                value, available
+            ),
+            (En, Text::UnknownModule { value, available }) => format!(
+                "Error: Unknown module `{}` (available values: {})",
+                value, available
+            ),
+            (De, Text::UnknownSubcommand(v)) => format!("Fehler: Unbekannter Befehl `{}`", v),
+            (En, Text::UnknownSubcommand(v)) => format!("Error: Unknown subcommand `{}`", v),
+            (De, Text::UserNotFound) => "Fehler: Benutzer nicht gefunden (Direktnachrichten \
+                                         können mit `@lsfbot dm enable` aktiviert werden)"
+                .to_owned(),
+            (En, Text::UserNotFound) => {
+                "Error: User not found (DMs can be enabled with `@lsfbot dm enable`)".to_owned()
+            }
+            (De, Text::MissingManageRoles) => {
+                "Fehler: Dem Bot fehlt die Berechtigung `Rollen verwalten`".to_owned()
+            }
+            (En, Text::MissingManageRoles) => {
+                "Error: The bot lacks the `Manage Roles` permission".to_owned()
+            }
+            (De, Text::RoleAboveBot(role)) => format!(
+                "Fehler: Die Rolle `{}` steht nicht unter der höchsten Rolle des Bots",
+                role
+            ),
+            (En, Text::RoleAboveBot(role)) => {
+                format!(
+                    "Error: The role `{}` is not below the highest role of the bot",
+                    role
+                )
+            }
+            (De, Text::Verspaetet) => {
+                "_Verspätet gesendet, da der Bot nicht erreichbar war_".to_owned()
+            }
+            (En, Text::Verspaetet) => "_Sent late because the bot was offline_".to_owned(),
+            (De, Text::LaeuftGerade) => "_Läuft gerade_".to_owned(),
+            (En, Text::LaeuftGerade) => "_In progress_".to_owned(),
+            (De, Text::Beendet) => "_Beendet_".to_owned(),
+            (En, Text::Beendet) => "_Ended_".to_owned(),
+            (De, Text::Updated) => "Stundenplan wurde aktualisiert".to_owned(),
+            (En, Text::Updated) => "Updated the schedule".to_owned(),
+            (De, Text::Configuration) => "Konfiguration".to_owned(),
+            (En, Text::Configuration) => "Configuration".to_owned(),
+            (_, Text::Status) => "Status".to_owned(),
+            (De, Text::LastSuccess) => "Letzte Aktualisierung".to_owned(),
+            (En, Text::LastSuccess) => "Last refresh".to_owned(),
+            (De, Text::LastFailure) => "Letzter Fehler".to_owned(),
+            (En, Text::LastFailure) => "Last failure".to_owned(),
+            (De, Text::NextRun) => "Nächste Aktualisierung".to_owned(),
+            (En, Text::NextRun) => "Next refresh".to_owned(),
+            (_, Text::Online) => "Online".to_owned(),
+            (De, Text::Art) => "Art".to_owned(),
+            (En, Text::Art) => "Type".to_owned(),
+            (De, Text::Dozent) => "Dozent".to_owned(),
+            (En, Text::Dozent) => "Lecturer".to_owned(),
+            (De, Text::Raum) => "Raum".to_owned(),
+            (En, Text::Raum) => "Room".to_owned(),
+            (De, Text::Rhythmus) => "Rhythmus".to_owned(),
+            (En, Text::Rhythmus) => "Rhythm".to_owned(),
+            (De, Text::Dauer) => "Dauer".to_owned(),
+            (En, Text::Dauer) => "Duration".to_owned(),
+            (De, Text::Bemerkung) => "Bemerkung".to_owned(),
+            (En, Text::Bemerkung) => "Note".to_owned(),
+            (De, Text::FaelltAus) => "Fällt aus".to_owned(),
+            (En, Text::FaelltAus) => "Cancelled".to_owned(),
+            (De, Text::Stundenplanaenderung) => "Stundenplanänderung".to_owned(),
+            (En, Text::Stundenplanaenderung) => "Schedule change".to_owned(),
+            (De, Text::NeuerTermin) => "Neuer Termin".to_owned(),
+            (En, Text::NeuerTermin) => "New date".to_owned(),
+            (De, Text::Entfaellt) => "Entfällt".to_owned(),
+            (En, Text::Entfaellt) => "Removed".to_owned(),
+            (De, Text::Vorher) => "Vorher".to_owned(),
+            (En, Text::Vorher) => "Before".to_owned(),
+            (De, Text::Jetzt) => "Jetzt".to_owned(),
+            (En, Text::Jetzt) => "Now".to_owned(),
+            (De, Text::TermineUm(time)) => format!("Veranstaltungen um {}", time),
+            (En, Text::TermineUm(time)) => format!("Dates at {}", time),
+            (De, Text::Stundenplan { wochentag, date }) => {
+                format!("Stundenplan für {}, {}", wochentag, date)
+            }
+            (En, Text::Stundenplan { wochentag, date }) => {
+                format!("Schedule for {}, {}", wochentag, date)
+            }
+            (De, Text::KeineVeranstaltungen) => "Keine Lehrveranstaltungen".to_owned(),
+            (En, Text::KeineVeranstaltungen) => "No lectures".to_owned(),
+            (De, Text::AbweichenderRhythmus(v)) => format!("Abweichender Rhythmus: {}", v),
+            (En, Text::AbweichenderRhythmus(v)) => format!("Different rhythm: {}", v),
+            (De, Text::Einstellung(option)) => {
+                let label = match option {
+                    "enabled" => "Aktiviert",
+                    "send-before" => "Erinnerungen vorher",
+                    "send-after-previous" => "Erinnerung nach der vorherigen Veranstaltung",
+                    "digest" => "Tagesübersicht",
+                    "quiet-hours" => "Ruhezeit",
+                    "pause" => "Pause",
+                    "cohorts" => "Kohorten",
+                    "modules" => "Module",
+                    "language" => "Sprache",
+                    "timezone" => "Zeitzone",
+                    v => v,
+                };
+                format!("{} (`{}`)", label, option)
+            }
+            (En, Text::Einstellung(option)) => option.to_owned(),
+            (De, Text::An) => "an".to_owned(),
+            (En, Text::An) => "on".to_owned(),
+            (De, Text::Aus) => "aus".to_owned(),
+            (En, Text::Aus) => "off".to_owned(),
+            (De, Text::Keine) => "keine".to_owned(),
+            (En, Text::Keine) => "none".to_owned(),
+            (De, Text::Standard) => "Standard".to_owned(),
+            (En, Text::Standard) => "default".to_owned(),
+            (De, Text::Bis(date)) => format!("bis {}", date),
+            (En, Text::Bis(date)) => format!("until {}", date),
+            (
+                De,
+                Text::Abo {
+                    vorlesungen,
+                    uebungen,
+                },
+            ) => format!("Vorlesungen: {}, Übungen: {}", vorlesungen, uebungen),
+            (
+                En,
+                Text::Abo {
+                    vorlesungen,
+                    uebungen,
+                },
+            ) => format!("lectures: {}, exercises: {}", vorlesungen, uebungen),
+        }
+    }
+
+    pub fn wochentag(&self, weekday: Weekday) -> &'static str {
+        match self.sprache {
+            Sprache::De => match weekday {
+                Weekday::Mon => "Montag",
+                Weekday::Tue => "Dienstag",
+                Weekday::Wed => "Mittwoch",
+                Weekday::Thu => "Donnerstag",
+                Weekday::Fri => "Freitag",
+                Weekday::Sat => "Samstag",
+                Weekday::Sun => "Sonntag",
+            },
+            Sprache::En => match weekday {
+                Weekday::Mon => "Monday",
+                Weekday::Tue => "Tuesday",
+                Weekday::Wed => "Wednesday",
+                Weekday::Thu => "Thursday",
+                Weekday::Fri => "Friday",
+                Weekday::Sat => "Saturday",
+                Weekday::Sun => "Sunday",
+            },
+        }
+    }
+
+    /// Converts a time into the time zone of the locale
+    pub fn local<T: TimeZone>(&self, time: &DateTime<T>) -> DateTime<Tz> {
+        time.with_timezone(&self.zeitzone)
+    }
+
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        match self.sprache {
+            Sprache::De => date.format("%d.%m.%Y").to_string(),
+            Sprache::En => date.format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    /// Parses a date in either of the formats of [`Locale::format_date`]
+    pub fn parse_date(&self, input: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(input, "%d.%m.%Y")
+            .or_else(|_| NaiveDate::parse_from_str(input, "%Y-%m-%d"))
+            .ok()
+    }
+
+    /// Formats the time until a future date (e.g. `in 1 Stunde und 5 Minuten`)
+    pub fn relative(&self, duration: Duration) -> String {
+        match self.sprache {
+            Sprache::De => {
+                let minutes = (duration.num_seconds() + 59) / 60;
+                let (hours, minutes) = (minutes / 60, minutes % 60);
+                let unit = |n: i64, singular: &str, plural: &str| {
+                    format!("{} {}", n, if n == 1 { singular } else { plural })
+                };
+                let parts = [
+                    Some(hours)
+                        .filter(|v| *v > 0)
+                        .map(|v| unit(v, "Stunde", "Stunden")),
+                    Some(minutes)
+                        .filter(|v| *v > 0 || hours == 0)
+                        .map(|v| unit(v, "Minute", "Minuten")),
+                ];
+                format!(
+                    "in {}",
+                    parts
+                        .iter()
+                        .flatten()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(" und ")
+                )
+            }
+            Sprache::En => HumanTime::from(duration).to_text_en(Accuracy::Precise, Tense::Future),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(sprache: Sprache) -> Locale {
+        Locale {
+            sprache,
+            ..Locale::default()
+        }
+    }
+
+    #[test]
+    fn relative() {
+        let de = locale(Sprache::De);
+        assert_eq!(
+            de.relative(Duration::minutes(65)),
+            "in 1 Stunde und 5 Minuten"
+        );
+        assert_eq!(de.relative(Duration::minutes(120)), "in 2 Stunden");
+        assert_eq!(de.relative(Duration::seconds(30)), "in 1 Minute");
+        let en = locale(Sprache::En);
+        assert_eq!(en.relative(Duration::minutes(120)), "in 2 hours");
+    }
+
+    #[test]
+    fn dates() {
+        let date = NaiveDate::from_ymd(2021, 1, 6);
+        assert_eq!(locale(Sprache::De).format_date(date), "06.01.2021");
+        assert_eq!(locale(Sprache::En).format_date(date), "2021-01-06");
+        assert_eq!(locale(Sprache::En).parse_date("06.01.2021"), Some(date));
+        assert_eq!(locale(Sprache::De).parse_date("2021-01-06"), Some(date));
+    }
+}
@@ -1,31 +1,63 @@
 use anyhow::{Context as _, Result};
-use chrono::{NaiveDate, TimeZone, Utc};
-use chrono_humanize::HumanTime;
+use chrono::{NaiveTime, Utc};
 use clap::Clap;
-use config::Config;
-use modul::{Modul, ModulGruppe, ModulTermin};
+use config::{CatchUp, Cleanup, Config};
+use diff::Change;
+use locale::{Locale, Sprache, Text};
+use modul::{MessageData, Modul, ModulTermin};
+use scheduler::{Aktion, Erinnerung};
 use serenity::client::{Client, Context, EventHandler};
 use serenity::framework::standard::macros::{command, group, help};
 use serenity::framework::standard::{
     help_commands, Args, CommandGroup, CommandResult, HelpOptions, StandardFramework,
 };
-use serenity::model::{channel::Message, id::ChannelId, id::UserId};
+use serenity::http::Http;
+use serenity::model::channel::{Message, PrivateChannel};
+use serenity::model::guild::{Guild, Member};
+use serenity::model::{id::ChannelId, id::GuildId, id::UserId};
 use serenity::prelude::TypeMapKey;
-use std::sync::{Arc, Mutex};
-use std::{collections::HashSet, fs, io, thread, time::Duration};
-use user::Users;
+use std::sync::{mpsc, Arc, Mutex};
+use std::{collections::HashSet, fs, io, mem, thread, time::Duration};
+use user::{Mitgliedschaft, User, UserStore};
+use zustellung::{Nachricht, Protokoll};
 
 mod arg;
 mod config;
+mod diff;
+mod digest;
+#[cfg(test)]
+mod fixture;
+mod locale;
 mod modul;
-mod scraper;
+mod persist;
+mod preview;
+mod refresh;
+mod rollen;
+mod scheduler;
+mod snapshot;
+mod source;
 mod user;
+mod zustellung;
 
-const SLEEP_SECS: u64 = 60;
+/// Interval in which the reminders are rebuilt to pick up changes of the users made outside of
+/// the bot
+const RESCHEDULE_SECS: u64 = 5 * 60;
+const MAX_EMBED_FIELDS: usize = 25;
 
 struct Data {
     module: Vec<Modul>,
-    users: Users,
+    users: Box<dyn UserStore>,
+    refresh: refresh::Status,
+    rollen: rollen::Rollen,
+    /// Wakes the reminder thread to rebuild the reminders
+    wecker: mpsc::Sender<()>,
+}
+
+impl Data {
+    /// Rebuilds the reminders after the schedule or the settings of a user changed
+    fn reschedule(&self) {
+        let _ = self.wecker.send(());
+    }
 }
 
 impl TypeMapKey for Data {
@@ -33,12 +65,12 @@ impl TypeMapKey for Data {
 }
 
 #[group]
-#[commands(list, update)]
+#[commands(list, update, status)]
 struct General;
 
 #[group]
 #[prefixes("dm")]
-#[commands(enable, disable, remove, set, get)]
+#[commands(enable, disable, remove, pause, set, get)]
 struct DirectMessages;
 
 #[command]
@@ -46,32 +78,22 @@ fn list(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
     let map = ctx.data.read();
     let config = map.get::<Config>().unwrap();
     let data = map.get::<Data>().unwrap();
-    let data_lock = data.lock().unwrap();
-
-    let author_group = match data_lock.users.get(msg.author.id) {
-        Some(user) => user.gruppe.clone(),
+    let stored = data
+        .lock()
+        .unwrap()
+        .users
+        .get(msg.author.id)
+        .map(|user| user.kohorten.clone());
+    let author_kohorten = match stored {
+        Some(v) => v,
         None => {
-            let user_has_role = |role_id: u64| match msg.author.has_role(
-                &ctx.http,
-                config.discord.guild_id,
-                role_id,
-            ) {
-                Ok(v) => v,
-                Err(_) => false,
-            };
-            if user_has_role(config.discord.gruppe_1.role_id) {
-                Some(ModulGruppe::Gruppe1)
-            } else if user_has_role(config.discord.gruppe_2.role_id) {
-                Some(ModulGruppe::Gruppe2)
-            } else if user_has_role(config.discord.gruppe_3.role_id) {
-                Some(ModulGruppe::Gruppe3)
-            } else if user_has_role(config.discord.gruppe_4.role_id) {
-                Some(ModulGruppe::Gruppe4)
-            } else {
-                None
-            }
+            let roles = member_roles(&ctx.http, config, data, msg.author.id).unwrap_or_default();
+            user::detect_kohorten(config, |role_id| roles.contains(&role_id))
         }
     };
+    let data_lock = data.lock().unwrap();
+    let author = data_lock.users.get(msg.author.id);
+    let locale = author.map(User::locale).unwrap_or_default();
 
     let get_messages = |filter: Box<dyn Fn(&ModulTermin) -> bool>| {
         data_lock
@@ -79,44 +101,49 @@ fn list(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
             .iter()
             .flat_map(|modul| modul.messages(|termin| filter(termin)))
             .filter(|message| {
-                message.modul.gruppe.is_none() || message.modul.gruppe == author_group
+                user::is_visible(
+                    &author_kohorten,
+                    author.map(|user| &user.abo),
+                    message.modul,
+                )
             })
             .collect::<Vec<_>>()
     };
 
     let mut messages = match args.current() {
-        Some(arg) => match NaiveDate::parse_from_str(arg, "%d.%m.%Y") {
-            Ok(v) => {
-                let date = chrono_tz::Europe::Berlin.from_local_date(&v).unwrap();
-                let messages = get_messages(Box::new(|termin| termin.beginn.date() == date));
+        Some(arg) => match locale.parse_date(arg) {
+            Some(date) => {
+                let messages = get_messages(Box::new(|termin| {
+                    locale.local(&termin.beginn).naive_local().date() == date
+                }));
                 if messages.is_empty() {
                     msg.channel_id.send_message(&ctx.http, |m| {
-                        m.content(format!(
-                            "Keine Lehrveranstaltungen am {}",
-                            date.format("%d.%m.%Y")
-                        ))
+                        m.content(
+                            locale.text(Text::KeineVeranstaltungenAm(&locale.format_date(date))),
+                        )
                     })?;
                     return Ok(());
                 }
                 messages
             }
-            Err(_) => {
-                msg.reply(&ctx.http, "Error: Invalid date format")?;
+            None => {
+                msg.reply(&ctx.http, locale.text(Text::InvalidDateFormat))?;
                 return Ok(());
             }
         },
         None => {
-            let mut date = Utc::now().date();
+            let mut date = locale.local(&Utc::now()).naive_local().date();
             let mut messages = Vec::new();
             for _ in 0..7 {
                 let date2 = date;
                 messages.extend(get_messages(Box::new(move |termin| {
-                    termin.beginn.date() == date2 && termin.ende > Utc::now()
+                    locale.local(&termin.beginn).naive_local().date() == date2
+                        && termin.ende > Utc::now()
                 })));
                 if !messages.is_empty() {
                     break;
                 }
-                date = date + chrono::Duration::days(1);
+                date += chrono::Duration::days(1);
             }
             messages
         }
@@ -125,7 +152,7 @@ fn list(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
     messages.sort_by_key(|m| m.modul_termin.beginn);
     for message in messages {
         msg.channel_id
-            .send_message(&ctx.http, |m| message.to_create_message(m, &config))?;
+            .send_message(&ctx.http, |m| message.to_create_message(m, config, &locale))?;
     }
     Ok(())
 }
@@ -133,11 +160,15 @@ fn list(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
 /// Enables direct messages
 #[command]
 fn enable(ctx: &mut Context, msg: &Message) -> CommandResult {
-    let mut map = ctx.data.write();
-    let data = map.get_mut::<Data>().unwrap();
-    match data.lock().unwrap().users.enable(msg.author.id) {
-        Ok(_) => msg.reply(&ctx.http, "Enabled direct messages")?,
-        Err(e) => msg.reply(&ctx.http, format!("Error: {}", e))?,
+    let map = ctx.data.read();
+    let config = map.get::<Config>().unwrap();
+    let data = map.get::<Data>().unwrap();
+    let locale = user_locale(data, msg.author.id);
+    match add_user(&ctx.http, config, data, msg.author.id)
+        .and_then(|_| data.lock().unwrap().users.enable(msg.author.id))
+    {
+        Ok(_) => msg.reply(&ctx.http, locale.text(Text::Enabled))?,
+        Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
     };
     Ok(())
 }
@@ -147,9 +178,10 @@ fn enable(ctx: &mut Context, msg: &Message) -> CommandResult {
 fn disable(ctx: &mut Context, msg: &Message) -> CommandResult {
     let mut map = ctx.data.write();
     let data = map.get_mut::<Data>().unwrap();
+    let locale = user_locale(data, msg.author.id);
     match data.lock().unwrap().users.disable(msg.author.id) {
-        Ok(_) => msg.reply(&ctx.http, "Disabled direct messages")?,
-        Err(e) => msg.reply(&ctx.http, format!("Error: {}", e))?,
+        Ok(_) => msg.reply(&ctx.http, locale.text(Text::Disabled))?,
+        Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
     };
     Ok(())
 }
@@ -159,12 +191,52 @@ fn disable(ctx: &mut Context, msg: &Message) -> CommandResult {
 fn remove(ctx: &mut Context, msg: &Message) -> CommandResult {
     let mut map = ctx.data.write();
     let data = map.get_mut::<Data>().unwrap();
+    let locale = user_locale(data, msg.author.id);
     match data.lock().unwrap().users.remove(msg.author.id) {
-        Ok(_) => msg.reply(
-            &ctx.http,
-            "Disabled direct messages and removed configuration",
-        )?,
-        Err(e) => msg.reply(&ctx.http, format!("Error: {}", e))?,
+        Ok(_) => msg.reply(&ctx.http, locale.text(Text::Removed))?,
+        Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
+    };
+    Ok(())
+}
+
+/// Pauses direct messages up to and including a date
+///
+/// Takes either `until` followed by a date (e.g. `until 06.01.2021`) or `off` as value
+#[command]
+fn pause(ctx: &mut Context, msg: &Message, args: Args) -> CommandResult {
+    let map = ctx.data.read();
+    let config = map.get::<Config>().unwrap();
+    let data = map.get::<Data>().unwrap();
+    let locale = user_locale(data, msg.author.id);
+    let arg = args.rest().trim();
+    let date = match arg.strip_prefix("until") {
+        Some(v) => match locale.parse_date(v.trim()) {
+            Some(v) => Some(v),
+            None => {
+                msg.reply(&ctx.http, locale.text(Text::InvalidDateFormat))?;
+                return Ok(());
+            }
+        },
+        None if arg == "off" => None,
+        None => {
+            msg.reply(
+                &ctx.http,
+                locale.text(Text::UnknownValue {
+                    value: arg,
+                    available: "`until <date>`, `off`",
+                }),
+            )?;
+            return Ok(());
+        }
+    };
+    match add_user(&ctx.http, config, data, msg.author.id)
+        .and_then(|_| data.lock().unwrap().users.set_pause(msg.author.id, date))
+    {
+        Ok(_) => match date {
+            Some(v) => msg.reply(&ctx.http, locale.text(Text::Paused(&locale.format_date(v))))?,
+            None => msg.reply(&ctx.http, locale.text(Text::Resumed))?,
+        },
+        Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
     };
     Ok(())
 }
@@ -172,40 +244,79 @@ fn remove(ctx: &mut Context, msg: &Message) -> CommandResult {
 /// Modifies configuration options for direct messages
 ///
 /// Available subcommands:
-/// - `send-before`: Takes either a number or `off` as value
+/// - `send-before`: Takes either a comma separated list of minutes (e.g. `60,10`) or `off` as
+///   value
 /// - `send-after-previous`: Takes either `on` or `off` as value
-/// - `group`: Takes either `1`, `2`, `3`, `4`, or `none` as value
+/// - `cohorts`: Takes a list of cohort identifiers (e.g. `ain1 win1`) or `none` as value
+/// - `group`: Takes an optional cohort identifier followed by either the name of a group (e.g.
+///   `1` for `Gruppe 1`), `none` or `auto` (follow the Discord roles) as value, the cohort can be
+///   omitted if the user belongs to exactly one cohort, the group roles are assigned as well if
+///   `assign_roles` is enabled
+/// - `quiet-hours`: Takes either a time span (e.g. `22:00-07:00`) or `off` as value
+/// - `digest`: Takes either a time (e.g. `07:00`) or `off` as value
+/// - `modules`: Takes either a module (e.g. `digitaltechnik` or `ain1/digitaltechnik`),
+///   `lectures` or `exercises` followed by `on` or `off`, or `all` to receive messages for every
+///   module again
+/// - `language`: Takes either `de` or `en` as value
+/// - `timezone`: Takes either the name of a time zone (e.g. `Europe/London`) or `default` as value
 #[command]
 fn set(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
-    let mut map = ctx.data.write();
-    let data = map.get_mut::<Data>().unwrap();
+    let map = ctx.data.read();
+    let config = map.get::<Config>().unwrap();
+    let data = map.get::<Data>().unwrap();
+    let locale = user_locale(data, msg.author.id);
+    if let Err(e) = add_user(&ctx.http, config, data, msg.author.id) {
+        msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?;
+        return Ok(());
+    }
     match args.single::<String>().unwrap().as_str() {
         "send-before" => {
-            let arg = args.single::<String>().unwrap();
-            let duration = match arg.as_str() {
-                "off" => None,
-                v => match v.parse::<u64>() {
-                    Ok(v) => Some(user::Duration { minutes: v }),
-                    Err(_) => {
-                        msg.reply(
-                            &ctx.http,
-                            format!(
-                                "Error: Unknown value `{}` (available values: number, `off`)",
-                                v
-                            ),
-                        )?;
-                        return Ok(());
+            let arg = args.rest().trim();
+            let mut durations = Vec::new();
+            if arg != "off" {
+                for v in arg.split(|c: char| c == ',' || c.is_whitespace()) {
+                    match v.parse::<u64>() {
+                        Ok(v) => durations.push(user::Duration { minutes: v }),
+                        Err(_) if v.is_empty() => {}
+                        Err(_) => {
+                            msg.reply(
+                                &ctx.http,
+                                locale.text(Text::UnknownValue {
+                                    value: v,
+                                    available: "`60,10`, `off`",
+                                }),
+                            )?;
+                            return Ok(());
+                        }
                     }
-                },
-            };
+                }
+                if durations.is_empty() {
+                    msg.reply(
+                        &ctx.http,
+                        locale.text(Text::UnknownValue {
+                            value: arg,
+                            available: "`60,10`, `off`",
+                        }),
+                    )?;
+                    return Ok(());
+                }
+            }
+            durations.sort_by_key(|v| std::cmp::Reverse(v.minutes));
+            durations.dedup();
             match data
                 .lock()
                 .unwrap()
                 .users
-                .set_send_before(msg.author.id, duration)
+                .set_send_before(msg.author.id, durations)
             {
-                Ok(_) => msg.reply(&ctx.http, format!("Set `send-before` to `{}`", arg))?,
-                Err(e) => msg.reply(&ctx.http, format!("Error: {}", e))?,
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::Set {
+                        option: "send-before",
+                        value: arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
             };
         }
         "send-after-previous" => {
@@ -216,10 +327,10 @@ fn set(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
                 v => {
                     msg.reply(
                         &ctx.http,
-                        format!(
-                            "Error: Unknown value `{}` (available values: `on`, `off`)",
-                            v
-                        ),
+                        locale.text(Text::UnknownValue {
+                            value: v,
+                            available: "`on`, `off`",
+                        }),
                     )?;
                     return Ok(());
                 }
@@ -230,73 +341,526 @@ fn set(ctx: &mut Context, msg: &Message, mut args: Args) -> CommandResult {
                 .users
                 .set_send_after(msg.author.id, enable)
             {
-                Ok(_) => msg.reply(&ctx.http, format!("Set `send-after-previous` to `{}`", arg))?,
-                Err(e) => msg.reply(&ctx.http, format!("Error: {}", e))?,
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::Set {
+                        option: "send-after-previous",
+                        value: &arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
+            };
+        }
+        "cohorts" => {
+            let arg = args.rest().trim();
+            let mut kohorten = Vec::new();
+            if arg != "none" {
+                for id in arg.split_whitespace() {
+                    match config.kohorte(id) {
+                        Some(kohorte) => kohorten.push(kohorte.id.clone()),
+                        None => {
+                            msg.reply(
+                                &ctx.http,
+                                locale.text(Text::UnknownCohort {
+                                    value: id,
+                                    available: &format!("{}, `none`", available_kohorten(config)),
+                                }),
+                            )?;
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            match data
+                .lock()
+                .unwrap()
+                .users
+                .set_kohorten(msg.author.id, kohorten)
+            {
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::Set {
+                        option: "cohorts",
+                        value: arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
             };
         }
         "group" => {
-            let (arg, group) = match args.single::<String>().as_deref() {
-                Ok("1") => ("1", Some(ModulGruppe::Gruppe1)),
-                Ok("2") => ("2", Some(ModulGruppe::Gruppe2)),
-                Ok("3") => ("3", Some(ModulGruppe::Gruppe3)),
-                Ok("4") => ("4", Some(ModulGruppe::Gruppe4)),
-                Ok("none") => ("none", None),
-                Ok(_) | Err(_) => {
+            let mut values = args.rest().split_whitespace().collect::<Vec<_>>();
+            let kohorte = match values.first().and_then(|v| config.kohorte(v)) {
+                Some(kohorte) => {
+                    values.remove(0);
+                    kohorte
+                }
+                None => {
+                    let data_lock = data.lock().unwrap();
+                    let kohorten = data_lock
+                        .users
+                        .get(msg.author.id)
+                        .map(|user| user.kohorten.as_slice())
+                        .unwrap_or_default();
+                    // The stored cohort may have been removed from the config since
+                    let kohorte = match (kohorten, config.kohorten.as_slice()) {
+                        ([mitgliedschaft], _) => config.kohorte(&mitgliedschaft.kohorte),
+                        ([], [kohorte]) => Some(kohorte),
+                        _ => None,
+                    };
+                    match kohorte {
+                        Some(v) => v,
+                        None => {
+                            msg.reply(
+                                &ctx.http,
+                                locale.text(Text::MissingCohort {
+                                    available: &available_kohorten(config),
+                                }),
+                            )?;
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+            let arg = values.join(" ");
+            let (group, manuell) = match arg.as_str() {
+                "none" => (None, true),
+                "auto" => {
+                    let roles =
+                        member_roles(&ctx.http, config, data, msg.author.id).unwrap_or_default();
+                    (
+                        kohorte.find_group(|role_id| roles.contains(&role_id)),
+                        false,
+                    )
+                }
+                v => match kohorte.parse_group(v) {
+                    Some(group) => (Some(group.gruppe()), true),
+                    None => {
+                        let available = kohorte
+                            .groups
+                            .iter()
+                            .map(|group| format!("`{}`", group.short_name()))
+                            .collect::<Vec<_>>();
+                        msg.reply(
+                            &ctx.http,
+                            locale.text(Text::UnknownValue {
+                                value: v,
+                                available: &format!("{}, `none`, `auto`", available.join(", ")),
+                            }),
+                        )?;
+                        return Ok(());
+                    }
+                },
+            };
+            if config.discord.assign_roles && manuell {
+                let guild_id = config.discord.guild_id;
+                let error = match rollen::missing_permission(&ctx.http, guild_id, kohorte) {
+                    Ok(None) => member_roles(&ctx.http, config, data, msg.author.id)
+                        .and_then(|roles| {
+                            let user_id = msg.author.id;
+                            let gruppe = group.as_ref();
+                            rollen::assign_group(
+                                &ctx.http, guild_id, user_id, &roles, kohorte, gruppe,
+                            )
+                        })
+                        .err()
+                        .map(|e| locale.text(Text::Error(&format!("{:#}", e)))),
+                    Ok(Some(rollen::Fehlend::ManageRoles)) => {
+                        Some(locale.text(Text::MissingManageRoles))
+                    }
+                    Ok(Some(rollen::Fehlend::Position(role))) => {
+                        Some(locale.text(Text::RoleAboveBot(&role)))
+                    }
+                    Err(e) => Some(locale.text(Text::Error(&format!("{:#}", e)))),
+                };
+                if let Some(error) = error {
+                    msg.reply(&ctx.http, error)?;
+                    return Ok(());
+                }
+            }
+            match data
+                .lock()
+                .unwrap()
+                .users
+                .set_group(msg.author.id, &kohorte.id, group, manuell)
+            {
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::SetGroup {
+                        kohorte: &kohorte.id,
+                        value: &arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
+            };
+        }
+        "quiet-hours" => {
+            let arg = args.rest().trim();
+            let ruhezeit = match arg {
+                "off" => None,
+                v => match v.parse::<user::Ruhezeit>() {
+                    Ok(v) => Some(v),
+                    Err(e) => {
+                        msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?;
+                        return Ok(());
+                    }
+                },
+            };
+            match data
+                .lock()
+                .unwrap()
+                .users
+                .set_ruhezeit(msg.author.id, ruhezeit)
+            {
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::Set {
+                        option: "quiet-hours",
+                        value: arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
+            };
+        }
+        "digest" => {
+            let arg = args.rest().trim();
+            let time = match arg {
+                "off" => None,
+                v => match NaiveTime::parse_from_str(v, "%H:%M") {
+                    Ok(v) => Some(v),
+                    Err(_) => {
+                        msg.reply(
+                            &ctx.http,
+                            locale.text(Text::UnknownValue {
+                                value: v,
+                                available: "`07:00`, `off`",
+                            }),
+                        )?;
+                        return Ok(());
+                    }
+                },
+            };
+            match data.lock().unwrap().users.set_digest(msg.author.id, time) {
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::Set {
+                        option: "digest",
+                        value: arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
+            };
+        }
+        "modules" => {
+            let data_lock = data.lock().unwrap();
+            let user = data_lock.users.get(msg.author.id);
+            let mut abo = user.map(|user| user.abo.clone()).unwrap_or_default();
+            let kohorten = user.map(|user| user.kohorten.clone()).unwrap_or_default();
+            drop(data_lock);
+            let arg = args.rest().trim().to_owned();
+            let mut values = arg.split_whitespace();
+            let target = values.next().unwrap_or_default();
+            let enable = match (target, values.next()) {
+                ("all", None) => None,
+                (_, Some("on")) => Some(true),
+                (_, Some("off")) => Some(false),
+                _ => {
+                    msg.reply(
+                        &ctx.http,
+                        locale.text(Text::UnknownValue {
+                            value: &arg,
+                            available: "`<module> on|off`, `lectures on|off`, \
+                                        `exercises on|off`, `all`",
+                        }),
+                    )?;
+                    return Ok(());
+                }
+            };
+            match (target, enable) {
+                (_, None) => abo = user::Abo::default(),
+                ("lectures", Some(v)) => abo.vorlesungen = v,
+                ("exercises", Some(v)) => abo.uebungen = v,
+                (v, Some(enable)) => {
+                    let keys = find_module(config, &kohorten, v);
+                    if keys.is_empty() {
+                        msg.reply(
+                            &ctx.http,
+                            locale.text(Text::UnknownModule {
+                                value: v,
+                                available: &available_module(config, &kohorten),
+                            }),
+                        )?;
+                        return Ok(());
+                    }
+                    abo.stumm.retain(|key| !keys.contains(key));
+                    if !enable {
+                        abo.stumm.extend(keys);
+                    }
+                }
+            }
+            match data.lock().unwrap().users.set_abo(msg.author.id, abo) {
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::Set {
+                        option: "modules",
+                        value: &arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
+            };
+        }
+        "language" => {
+            let arg = args.rest().trim();
+            let sprache = match arg.parse::<Sprache>() {
+                Ok(v) => v,
+                Err(_) => {
                     msg.reply(
                         &ctx.http,
-                        "Error: Invalid value (available values: `1`, `2`, `3`, `4`, `none`)",
+                        locale.text(Text::UnknownValue {
+                            value: arg,
+                            available: "`de`, `en`",
+                        }),
                     )?;
                     return Ok(());
                 }
             };
-            match data.lock().unwrap().users.set_group(msg.author.id, group) {
-                Ok(_) => msg.reply(&ctx.http, format!("Set `group` to `{}`", arg))?,
-                Err(e) => msg.reply(&ctx.http, format!("Error: {}", e))?,
+            // Confirms in the new language
+            let locale = Locale { sprache, ..locale };
+            match data
+                .lock()
+                .unwrap()
+                .users
+                .set_sprache(msg.author.id, sprache)
+            {
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::Set {
+                        option: "language",
+                        value: arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
+            };
+        }
+        "timezone" => {
+            let arg = args.rest().trim();
+            let zeitzone = match arg {
+                "default" => None,
+                v => match v.parse::<chrono_tz::Tz>() {
+                    Ok(v) => Some(v),
+                    Err(_) => {
+                        msg.reply(
+                            &ctx.http,
+                            locale.text(Text::UnknownValue {
+                                value: v,
+                                available: "`Europe/Berlin`, `Europe/London`, …, `default`",
+                            }),
+                        )?;
+                        return Ok(());
+                    }
+                },
+            };
+            match data
+                .lock()
+                .unwrap()
+                .users
+                .set_zeitzone(msg.author.id, zeitzone)
+            {
+                Ok(_) => msg.reply(
+                    &ctx.http,
+                    locale.text(Text::Set {
+                        option: "timezone",
+                        value: arg,
+                    }),
+                )?,
+                Err(e) => msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?,
             };
         }
         v => {
-            msg.reply(&ctx.http, format!("Error: Unknown subcommand `{}`", v))?;
+            msg.reply(&ctx.http, locale.text(Text::UnknownSubcommand(v)))?;
             return Ok(());
         }
     };
     Ok(())
 }
 
+/// Returns the locale of the author of a command, or the default locale for unknown users
+fn user_locale(data: &Mutex<Data>, user_id: UserId) -> Locale {
+    data.lock()
+        .unwrap()
+        .users
+        .get(user_id)
+        .map(User::locale)
+        .unwrap_or_default()
+}
+
+/// Returns the Discord roles of a member from the cache, members that are not cached yet are
+/// fetched without holding the lock
+fn member_roles(
+    http: &Http,
+    config: &Config,
+    data: &Mutex<Data>,
+    user_id: UserId,
+) -> Result<Vec<u64>> {
+    if let Some(roles) = data.lock().unwrap().rollen.get(user_id) {
+        return Ok(roles.to_vec());
+    }
+    let member = rollen::fetch(http, config.discord.guild_id, user_id)?;
+    data.lock().unwrap().rollen.insert(&member);
+    Ok(member.roles.iter().map(|role| role.0).collect())
+}
+
+/// Stores a user that is not stored yet, the cohorts are detected from the roles returned by
+/// [`member_roles`], so the lock is not held while they are fetched
+fn add_user(http: &Http, config: &Config, data: &Mutex<Data>, user_id: UserId) -> Result<()> {
+    if data.lock().unwrap().users.get(user_id).is_some() {
+        return Ok(());
+    }
+    let roles = member_roles(http, config, data, user_id).unwrap_or_default();
+    let mut data_lock = data.lock().unwrap();
+    if data_lock.users.get(user_id).is_some() {
+        return Ok(());
+    }
+    data_lock.users.put(user::new_user(config, user_id, &roles))
+}
+
+/// Lists the identifiers of all configured cohorts for error messages
+fn available_kohorten(config: &Config) -> String {
+    config
+        .kohorten
+        .iter()
+        .map(|kohorte| format!("`{}`", kohorte.id))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the modules of the cohorts of a user, or of all cohorts if the user has none
+fn user_module<'a>(
+    config: &'a Config,
+    kohorten: &'a [Mitgliedschaft],
+) -> impl Iterator<Item = (&'a config::Kohorte, &'a config::Modul)> {
+    config
+        .kohorten
+        .iter()
+        .filter(move |kohorte| {
+            kohorten.is_empty() || kohorten.iter().any(|v| v.kohorte == kohorte.id)
+        })
+        .flat_map(|kohorte| kohorte.module.iter().map(move |modul| (kohorte, modul)))
+}
+
+/// Returns the keys of the modules matching the value of `dm set modules`, which is either the
+/// identifier or the key of a module
+fn find_module(config: &Config, kohorten: &[Mitgliedschaft], input: &str) -> Vec<String> {
+    user_module(config, kohorten)
+        .map(|(kohorte, modul)| modul.typ(&kohorte.id).key())
+        .filter(|key| key == input || key.rsplit('/').next() == Some(input))
+        .collect()
+}
+
+/// Lists the identifiers of the modules of a user for error messages
+fn available_module(config: &Config, kohorten: &[Mitgliedschaft]) -> String {
+    user_module(config, kohorten)
+        .map(|(_, modul)| format!("`{}`", modul.id))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Displays the configuration
 #[command]
 fn get(ctx: &mut Context, msg: &Message) -> CommandResult {
     let map = ctx.data.read();
+    let config = map.get::<Config>().unwrap();
     let data = map.get::<Data>().unwrap();
     match data.lock().unwrap().users.get(msg.author.id) {
         Some(user) => {
-            let send_before_fmt = match &user.send_before {
-                Some(v) => format!("{}min", v.minutes),
-                None => "off".to_owned(),
-            };
-            let send_after_previous_fmt = if user.send_after_previous {
-                "on"
+            let locale = user.locale();
+            let on_off = |v: bool| locale.text(if v { Text::An } else { Text::Aus });
+            let send_before_fmt = if user.send_before.is_empty() {
+                locale.text(Text::Aus)
             } else {
-                "off"
+                user.send_before
+                    .iter()
+                    .map(|v| format!("{}min", v.minutes))
+                    .collect::<Vec<_>>()
+                    .join(", ")
             };
-            let group = match &user.gruppe {
+            let quiet_hours_fmt = match &user.ruhezeit {
                 Some(v) => v.to_string(),
-                None => "none".to_owned(),
+                None => locale.text(Text::Aus),
+            };
+            let pause_fmt = match user.pause_bis.filter(|_| user.is_paused(Utc::now())) {
+                Some(v) => locale.text(Text::Bis(&locale.format_date(v))),
+                None => locale.text(Text::Aus),
             };
+            let digest_fmt = match user.digest {
+                Some(v) => v.format("%H:%M").to_string(),
+                None => locale.text(Text::Aus),
+            };
+            let kohorten = user
+                .kohorten
+                .iter()
+                .map(|mitgliedschaft| {
+                    let name = config
+                        .kohorte(&mitgliedschaft.kohorte)
+                        .map_or(mitgliedschaft.kohorte.as_str(), |v| v.name.as_str());
+                    match &mitgliedschaft.gruppe {
+                        Some(gruppe) => format!("{} ({})", name, gruppe),
+                        None => name.to_owned(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let kohorten = if kohorten.is_empty() {
+                locale.text(Text::Keine)
+            } else {
+                kohorten.join(", ")
+            };
+            let module = user_module(config, &user.kohorten)
+                .map(|(kohorte, modul)| modul.typ(&kohorte.id))
+                .filter(|typ| !user.abo.stumm.contains(&typ.key()))
+                .map(|typ| typ.name)
+                .collect::<Vec<_>>();
+            let module = format!(
+                "{}\n{}",
+                if module.is_empty() {
+                    locale.text(Text::Keine)
+                } else {
+                    module.join(", ")
+                },
+                locale.text(Text::Abo {
+                    vorlesungen: &on_off(user.abo.vorlesungen),
+                    uebungen: &on_off(user.abo.uebungen),
+                })
+            );
+            let sprache_fmt = match user.sprache {
+                Sprache::De => "de",
+                Sprache::En => "en",
+            };
+            let zeitzone_fmt = match user.zeitzone {
+                Some(v) => v.name().to_owned(),
+                None => locale.text(Text::Standard),
+            };
+            let field = |option| locale.text(Text::Einstellung(option));
             msg.channel_id.send_message(&ctx.http, |m| {
                 m.embed(|e| {
-                    e.title("Configuration")
-                        .field("enabled", user.enabled, false)
-                        .field("send-before", send_before_fmt, false)
-                        .field("send-after-previous", send_after_previous_fmt, false)
-                        .field("group", group, false)
+                    e.title(locale.text(Text::Configuration))
+                        .field(field("enabled"), on_off(user.enabled), false)
+                        .field(field("send-before"), send_before_fmt, false)
+                        .field(
+                            field("send-after-previous"),
+                            on_off(user.send_after_previous),
+                            false,
+                        )
+                        .field(field("digest"), digest_fmt, false)
+                        .field(field("quiet-hours"), quiet_hours_fmt, false)
+                        .field(field("pause"), pause_fmt, false)
+                        .field(field("cohorts"), kohorten, false)
+                        .field(field("modules"), module, false)
+                        .field(field("language"), sprache_fmt, false)
+                        .field(field("timezone"), zeitzone_fmt, false)
                 })
             })?;
         }
         None => {
-            msg.reply(
-                &ctx.http,
-                "Error: User not found (DMs can be enabled with `@lsfbot dm enable`)",
-            )?;
+            msg.reply(&ctx.http, Locale::default().text(Text::UserNotFound))?;
         }
     };
     Ok(())
@@ -306,18 +870,164 @@ fn get(ctx: &mut Context, msg: &Message) -> CommandResult {
 fn update(ctx: &mut Context, msg: &Message) -> CommandResult {
     let map = ctx.data.read();
     let config = map.get::<Config>().unwrap();
-    let module = match scraper::fetch_module(&config) {
+    let data = map.get::<Data>().unwrap();
+    let locale = user_locale(data, msg.author.id);
+    let module = match source::fetch_module(config) {
         Ok(v) => v,
         Err(e) => {
-            msg.reply(&ctx.http, format!("Error: {}", e))?;
+            data.lock().unwrap().refresh.record_failure(&e);
+            msg.reply(&ctx.http, locale.text(Text::Error(&e.to_string())))?;
             return Ok(());
         }
     };
-    drop(map);
-    let mut map = ctx.data.write();
-    let data = map.get_mut::<Data>().unwrap();
-    data.lock().unwrap().module = module;
-    msg.reply(&ctx.http, "Stundenplan wurde aktualisiert")?;
+    apply_schedule(&ctx.http, config, data, module);
+    msg.reply(&ctx.http, locale.text(Text::Updated))?;
+    Ok(())
+}
+
+/// Displays when the schedule was last refreshed
+#[command]
+fn status(ctx: &mut Context, msg: &Message) -> CommandResult {
+    let map = ctx.data.read();
+    let data = map.get::<Data>().unwrap();
+    let data_lock = data.lock().unwrap();
+    let locale = data_lock
+        .users
+        .get(msg.author.id)
+        .map(User::locale)
+        .unwrap_or_default();
+    let status = &data_lock.refresh;
+    let format = |time: &chrono::DateTime<Utc>| {
+        format!(
+            "{} {}",
+            locale.format_date(locale.local(time).date().naive_local()),
+            locale.local(time).format("%H:%M")
+        )
+    };
+    let last_success = match &status.last_success {
+        Some(v) => format(v),
+        None => "-".to_owned(),
+    };
+    let last_failure = match &status.last_failure {
+        Some((time, error)) => format!("{}: {}", format(time), error),
+        None => "-".to_owned(),
+    };
+    let next_run = match &status.next_run {
+        Some(v) => format(v),
+        None => "-".to_owned(),
+    };
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(locale.text(Text::Status))
+                .field(locale.text(Text::LastSuccess), last_success, false)
+                .field(locale.text(Text::LastFailure), last_failure, false)
+                .field(locale.text(Text::NextRun), next_run, false)
+        })
+    })?;
+    Ok(())
+}
+
+/// Replaces the schedule, saves it as snapshot and announces the changes
+fn apply_schedule(http: &Http, config: &Config, data: &Mutex<Data>, module: Vec<Modul>) {
+    if let Err(e) = snapshot::save(config.snapshot_path(), &module) {
+        log::error!("{:#}", e);
+    }
+    let mut data_lock = data.lock().unwrap();
+    let old = mem::replace(&mut data_lock.module, module.clone());
+    data_lock.refresh.record_success();
+    data_lock.reschedule();
+    let users = data_lock.users.get_all().to_vec();
+    drop(data_lock);
+    let changes = diff::diff(&old, &module);
+    announce_changes(http, config, &users, &changes);
+}
+
+/// Posts upcoming schedule changes to the affected group channels and enabled users
+fn announce_changes(http: &Http, config: &Config, users: &[User], changes: &[Change]) {
+    let now = Utc::now();
+    let changes = changes
+        .iter()
+        .filter(|change| change.is_upcoming(now))
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return;
+    }
+    log::info!("Announcing {} schedule changes", changes.len());
+
+    let affected = |kohorten: &[Mitgliedschaft]| {
+        changes
+            .iter()
+            .copied()
+            .filter(|change| change.affects(kohorten))
+            .collect::<Vec<_>>()
+    };
+
+    for kohorte in &config.kohorten {
+        for group in &kohorte.groups {
+            let mitgliedschaft = Mitgliedschaft {
+                kohorte: kohorte.id.clone(),
+                gruppe: Some(group.gruppe()),
+                manuell: false,
+            };
+            for chunk in affected(&[mitgliedschaft]).chunks(MAX_EMBED_FIELDS) {
+                let mention = chunk
+                    .iter()
+                    .map(|change| config.channel_settings(&change.modul().typ, group).mention)
+                    .min()
+                    .unwrap_or_default();
+                match ChannelId(group.channel_id).send_message(http, |m| {
+                    if let Some(content) = mention.to_content(group) {
+                        m.content(content);
+                    }
+                    m.embed(|e| diff::to_embed(chunk, &Locale::default(), e))
+                }) {
+                    Ok(_) => log::info!("Sent schedule changes to channel `{}`", group.channel_id),
+                    Err(e) => log::error!("Failed to send schedule changes: {}", e),
+                }
+            }
+        }
+    }
+
+    for user in users
+        .iter()
+        .filter(|user| user.enabled && !user.is_paused(now) && !user.is_quiet(now))
+    {
+        let changes = affected(&user.kohorten)
+            .into_iter()
+            .filter(|change| user.abo.includes(change.modul()))
+            .collect::<Vec<_>>();
+        if changes.is_empty() {
+            continue;
+        }
+        log::debug!("Creating dm channel for user `{}`", user.id);
+        let channel = match user.id.create_dm_channel(http) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Failed to create dm channel for user `{}`: {}", user.id, e);
+                continue;
+            }
+        };
+        for chunk in changes.chunks(MAX_EMBED_FIELDS) {
+            match channel.send_message(http, |m| {
+                m.embed(|e| diff::to_embed(chunk, &user.locale(), e))
+            }) {
+                Ok(_) => log::info!("Sent schedule changes to dm channel `{}`", channel.id.0),
+                Err(e) => log::error!("Failed to send schedule changes: {}", e),
+            }
+        }
+    }
+}
+
+/// Copies the users of a TOML users file into the SQLite database configured in `users.file`
+fn import_users(config: &Config, file: &std::path::Path) -> Result<()> {
+    if !matches!(config.users.backend, config::Backend::Sqlite) {
+        return Err(anyhow::anyhow!(
+            "The users backend has to be set to `sqlite` for importing users"
+        ));
+    }
+    let users = user::file::read(file, config).context("Failed to read users file")?;
+    user::sqlite::import(&config.resolve_path(&config.users.file), &users)?;
+    log::info!("Imported {} users", users.len());
     Ok(())
 }
 
@@ -335,7 +1045,69 @@ fn help(
 
 struct Handler;
 
-impl EventHandler for Handler {}
+impl EventHandler for Handler {
+    fn guild_create(&self, ctx: Context, guild: Guild, _is_new: bool) {
+        let map = ctx.data.read();
+        let config = map.get::<Config>().unwrap();
+        let mut data_lock = map.get::<Data>().unwrap().lock().unwrap();
+        for member in guild.members.values() {
+            sync_member(config, &mut data_lock, member);
+        }
+    }
+
+    fn guild_member_addition(&self, ctx: Context, _guild_id: GuildId, member: Member) {
+        let map = ctx.data.read();
+        let config = map.get::<Config>().unwrap();
+        sync_member(
+            config,
+            &mut map.get::<Data>().unwrap().lock().unwrap(),
+            &member,
+        );
+    }
+
+    fn guild_member_update(&self, ctx: Context, _old: Option<Member>, member: Member) {
+        let map = ctx.data.read();
+        let config = map.get::<Config>().unwrap();
+        sync_member(
+            config,
+            &mut map.get::<Data>().unwrap().lock().unwrap(),
+            &member,
+        );
+    }
+
+    fn guild_member_removal(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        user: serenity::model::user::User,
+        _member: Option<Member>,
+    ) {
+        let map = ctx.data.read();
+        if guild_id.0 == map.get::<Config>().unwrap().discord.guild_id {
+            let mut data_lock = map.get::<Data>().unwrap().lock().unwrap();
+            data_lock.rollen.remove(user.id);
+        }
+    }
+}
+
+/// Caches the roles of a guild member and updates the groups of the user if the roles changed
+fn sync_member(config: &Config, data: &mut Data, member: &Member) {
+    if member.guild_id.0 != config.discord.guild_id || !data.rollen.insert(member) {
+        return;
+    }
+    let user_id = member.user.read().id;
+    let detected = user::detect_kohorten(config, |role_id| {
+        member.roles.iter().any(|role| role.0 == role_id)
+    });
+    match data.users.sync_roles(user_id, &detected) {
+        Ok(true) => {
+            log::debug!("Synchronized groups of user `{}`", user_id);
+            data.reschedule();
+        }
+        Ok(false) => {}
+        Err(e) => log::error!("Failed to update groups of user `{}`: {:#}", user_id, e),
+    }
+}
 
 fn main() -> Result<()> {
     fern::Dispatch::new()
@@ -356,26 +1128,24 @@ fn main() -> Result<()> {
     let args = arg::Args::parse();
 
     let config_data = fs::read_to_string(&args.config).context("Failed to read config file")?;
-    let config: Config =
+    let mut config: Config =
         toml::from_str(&config_data).context("Failed to deserialize config file")?;
+    config.directory = args.config.parent().unwrap().to_owned();
     let config = Arc::new(config);
 
-    let users_file_path = if config.users.file.is_absolute() {
-        config.users.file.clone()
-    } else {
-        args.config.parent().unwrap().join(&config.users.file)
-    };
+    if let Some(arg::Command::ImportUsers { file }) = &args.command {
+        return import_users(&config, file);
+    }
 
+    let (wecker, weckruf) = mpsc::channel();
     let mut client = Client::new(&config.discord.bot_token, Handler).unwrap();
 
     let data = Arc::new(Mutex::new(Data {
         module: Vec::new(),
-        users: Users::new(
-            users_file_path,
-            Arc::clone(&config),
-            Arc::clone(&client.cache_and_http),
-        )
-        .context("Failed to read users file")?,
+        users: user::from_config(Arc::clone(&config)).context("Failed to read users")?,
+        refresh: refresh::Status::default(),
+        rollen: rollen::Rollen::default(),
+        wecker,
     }));
 
     let http_client = Arc::clone(&client.cache_and_http.http);
@@ -383,6 +1153,16 @@ fn main() -> Result<()> {
     let framework = StandardFramework::new()
         .configure(|c| c.on_mention(Some(bot_id)))
         .help(&HELP)
+        // Commands may change the settings of the author
+        .after(|ctx, _msg, _name, _result| {
+            ctx.data
+                .read()
+                .get::<Data>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .reschedule();
+        })
         .group(&GENERAL_GROUP)
         .group(&DIRECTMESSAGES_GROUP);
     client.with_framework(framework);
@@ -398,184 +1178,350 @@ fn main() -> Result<()> {
         client.start()
     });
 
-    let module = scraper::fetch_module(&config).context("Failed to fetch data from website")?;
+    let previous = snapshot::load(config.snapshot_path());
+    let module = match source::fetch_module(&config) {
+        Ok(module) => {
+            if let Err(e) = snapshot::save(config.snapshot_path(), &module) {
+                log::error!("{:#}", e);
+            }
+            let mut data_lock = data.lock().unwrap();
+            data_lock.refresh.record_success();
+            let users = data_lock.users.get_all().to_vec();
+            drop(data_lock);
+            if let Ok(previous) = &previous {
+                let changes = diff::diff(&previous.module, &module);
+                announce_changes(&http_client, &config, &users, &changes);
+            }
+            module
+        }
+        Err(e) => {
+            data.lock().unwrap().refresh.record_failure(&e);
+            log::warn!("Falling back to schedule snapshot");
+            let snapshot = previous.context("Failed to fetch schedule and to load snapshot")?;
+            log::info!(
+                "Loaded schedule snapshot from {}",
+                snapshot.fetched_at.format("%d.%m.%Y %H:%M")
+            );
+            snapshot.module.into_owned()
+        }
+    };
     data.lock().unwrap().module = module;
 
-    let reminder_join_handle = thread::spawn(move || {
-        log::debug!("Checking for reminders");
-
-        let send_message =
-            |message: &modul::MessageData, group: &config::Group| match ChannelId(group.channel_id)
-                .send_message(&http_client, |m| {
-                    message
-                        .to_create_message(m, &config)
-                        .content(format!("<@&{}>", group.role_id))
-                }) {
-                Ok(_) => log::info!("Sent reminder message to channel `{}`", group.channel_id),
-                Err(e) => log::error!("Failed to send reminder message: {}", e),
-            };
+    let refresh_join_handle = {
+        let config = Arc::clone(&config);
+        let data = Arc::clone(&data);
+        let http_client = Arc::clone(&http_client);
+        thread::spawn(move || refresh::run(config, data, http_client))
+    };
 
-        let mut delay = chrono::Duration::zero();
+    let reminder_join_handle = thread::spawn(move || {
+        let mut protokoll = Protokoll::load(config.delivery_log_path()).unwrap_or_else(|e| {
+            log::error!("{:#}", e);
+            Protokoll::empty(config.delivery_log_path())
+        });
+        let started = Utc::now();
+        // Reminders up to this time have been handled, including the time before a restart
+        let mut sent_until = protokoll.bis().map_or(started, |v| v.min(started));
         loop {
-            let start = Utc::now();
-            log::debug!("Starting loop for reminder messages");
-            let mut data_lock = data.lock().unwrap();
-            data_lock.users.refresh().unwrap();
-            let module = &data_lock.module;
-
-            log::debug!("Checking messages for group channels");
-            let mut messages = module
-                .iter()
-                .flat_map(|modul| {
-                    modul.messages(|termin| {
-                        let duration = termin.beginn.signed_duration_since(Utc::now());
-                        duration.num_seconds() > 30 * 60 - SLEEP_SECS as i64 - delay.num_seconds()
-                            && duration.num_seconds() < 30 * 60
-                    })
-                })
-                .collect::<Vec<_>>();
-            messages.sort_by_key(|m| m.modul_termin.beginn);
-            for message in messages {
-                match message.modul.gruppe {
-                    Some(ModulGruppe::Gruppe1) => send_message(&message, &config.discord.gruppe_1),
-                    Some(ModulGruppe::Gruppe2) => send_message(&message, &config.discord.gruppe_2),
-                    Some(ModulGruppe::Gruppe3) => send_message(&message, &config.discord.gruppe_3),
-                    Some(ModulGruppe::Gruppe4) => send_message(&message, &config.discord.gruppe_4),
-                    None => {
-                        send_message(&message, &config.discord.gruppe_1);
-                        send_message(&message, &config.discord.gruppe_2);
-                        send_message(&message, &config.discord.gruppe_3);
-                        send_message(&message, &config.discord.gruppe_4);
-                    }
+            // Sending can take long, so the lock is only held while copying the schedule
+            let (module, users) = {
+                let mut data_lock = data.lock().unwrap();
+                if let Err(e) = data_lock.users.refresh() {
+                    log::error!("Failed to reload users: {:#}", e);
+                }
+                (data_lock.module.clone(), data_lock.users.get_all().to_vec())
+            };
+            let now = Utc::now();
+            let mut zeitplan = scheduler::Zeitplan::new(&config, &module, &users, sent_until, now);
+            let mut due = Vec::new();
+            while let Some(erinnerung) = zeitplan.pop_due(now) {
+                let zustellung = erinnerung.zustellung();
+                if protokoll.contains(&zustellung) {
+                    log::debug!("Skipping reminder {:?} (already sent)", zustellung);
+                    continue;
+                }
+                if erinnerung.zeit < started && config.reminders.catch_up == CatchUp::Skip {
+                    log::info!("Skipping reminder {:?} (missed while offline)", zustellung);
+                    continue;
+                }
+                // Late reminders, e.g. after a failure or a stall, are only sent while useful
+                if !erinnerung.is_current(now) {
+                    log::info!("Skipping reminder {:?} (no longer current)", zustellung);
+                    continue;
                 }
+                due.push(erinnerung);
             }
-            log::debug!("Finished checking messages for group channels");
-
-            log::debug!("Checking for user messages");
-            for user in data_lock.users.get_all() {
-                if !user.enabled {
-                    log::debug!("Skipping messages for user `{}` (disabled)", user.id);
+            // Failed reminders are included again when the schedule is rebuilt
+            let mut failed: Option<chrono::DateTime<Utc>> = None;
+            for batch in scheduler::batches(due) {
+                let late = batch[0].zeit < started;
+                let result =
+                    send_erinnerungen(&http_client, &config, &module, &batch, late, &mut protokoll);
+                if let Err(e) = result {
+                    log::error!("{:#}", e);
+                    let zeit = batch[0].zeit;
+                    failed = Some(failed.map_or(zeit, |v| v.min(zeit)));
                     continue;
                 }
-                log::debug!("Checking messages for user `{}`", user.id);
-                let messages = module
-                    .iter()
-                    .flat_map(|modul| {
-                        modul.messages(|termin| {
-                            let duration = termin.beginn.signed_duration_since(Utc::now());
-                            match user.send_before.as_ref().map(|v| v.minutes) {
-                                Some(minutes) => {
-                                    duration.num_seconds()
-                                        > (minutes * 60 - SLEEP_SECS) as i64 - delay.num_seconds()
-                                        && duration.num_seconds() < (minutes * 60) as i64
-                                        && (modul.gruppe.is_none() || modul.gruppe == user.gruppe)
-                                }
-                                None => false,
-                            }
-                        })
-                    })
-                    .collect::<Vec<_>>();
-                log::debug!("Finished checking for messages for user `{}`", user.id);
-
-                if !messages.is_empty() {
-                    log::debug!("Creating dm channel for user `{}`", user.id);
-                    let channel = match user.id.create_dm_channel(&http_client) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            log::error!(
-                                "Failed to create dm channel for user `{}`: {}",
-                                user.id,
-                                e
-                            );
-                            continue;
-                        }
-                    };
-                    for message in messages {
-                        match channel
-                            .send_message(&http_client, |m| message.to_create_message(m, &config))
-                        {
-                            Ok(_) => {
-                                log::info!("Sent reminder message to dm channel `{}`", channel.id.0)
-                            }
-                            Err(e) => log::error!("Failed to send reminder message: {}", e),
-                        };
-                    }
+                for erinnerung in &batch {
+                    protokoll.insert(erinnerung.zustellung(), erinnerung.ende);
                 }
+            }
+            let mark_running = config.reminders.cleanup == Cleanup::Edit;
+            for nachricht in protokoll.pop_nachrichten(now, mark_running) {
+                clean_up(&http_client, config.reminders.cleanup, &nachricht);
+            }
+            sent_until = failed.map_or(now, |v| v - chrono::Duration::milliseconds(1));
+            protokoll.advance(sent_until);
+            if let Err(e) = protokoll.save() {
+                log::error!("{:#}", e);
+            }
+            let next = match (zeitplan.next(), protokoll.next_nachricht(mark_running)) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
 
-                if user.send_after_previous {
-                    log::debug!(
-                        "Checking for `send-after-previous` message for user `{}`",
-                        user.id
-                    );
-                    let messages_today = module
-                        .iter()
-                        .flat_map(|modul| {
-                            modul.messages(|termin| {
-                                termin.beginn.date() == Utc::now().date()
-                                    && (modul.gruppe.is_none() || modul.gruppe == user.gruppe)
-                            })
-                        })
-                        .collect::<Vec<_>>();
-                    let last = messages_today
-                        .iter()
-                        .filter(|v| v.modul_termin.beginn < Utc::now())
-                        .map(|v| v.modul_termin.ende)
-                        .find(|v| {
-                            let duration = v.signed_duration_since(Utc::now());
-                            duration.num_seconds() > 0
-                                && duration.num_seconds() < SLEEP_SECS as i64 + delay.num_seconds()
-                        });
-                    let next_message = last.and_then(|last| {
-                        messages_today
-                            .into_iter()
-                            .filter(|v| v.modul_termin.beginn > last)
-                            .min_by_key(|v| v.modul_termin.beginn)
-                    });
-                    if let Some(message) = next_message {
-                        log::debug!("Creating dm channel for user `{}`", user.id);
-                        let channel = match user.id.create_dm_channel(&http_client) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log::error!(
-                                    "Failed to create dm channel for user `{}`: {}",
-                                    user.id,
-                                    e
-                                );
-                                continue;
-                            }
-                        };
-                        match channel.send_message(&http_client, |m| {
-                            let duration = HumanTime::from(
-                                message
-                                    .modul_termin
-                                    .beginn
-                                    .signed_duration_since(Utc::now()),
-                            );
-                            message
-                                .to_create_message(m, &config)
-                                .content(duration.to_text_en(
-                                    chrono_humanize::Accuracy::Precise,
-                                    chrono_humanize::Tense::Future,
-                                ))
-                        }) {
-                            Ok(_) => {
-                                log::info!("Sent reminder message to dm channel `{}`", channel.id.0)
-                            }
-                            Err(e) => log::error!("Failed to send reminder message: {}", e),
-                        };
-                    }
+            let max = Duration::from_secs(RESCHEDULE_SECS);
+            let timeout = match next {
+                Some(next) => {
+                    log::debug!("Next reminder at {}", next);
+                    let until = next.signed_duration_since(Utc::now());
+                    until.to_std().unwrap_or_default().min(max)
                 }
-                log::debug!("Finished checks for user `{}`", user.id);
+                None => max,
+            };
+            if weckruf.recv_timeout(timeout).is_ok() {
+                // Several changes at once only need a single rebuild
+                while weckruf.try_recv().is_ok() {}
+                log::debug!("Rebuilding reminders after a change");
             }
-            log::debug!("Finished checks");
-            drop(data_lock);
-            delay = Utc::now().signed_duration_since(start);
-            thread::sleep(Duration::from_secs(SLEEP_SECS));
         }
     });
 
     start_client_join_handle.join().unwrap()?;
     reminder_join_handle.join().unwrap();
+    refresh_join_handle.join().unwrap();
+
+    Ok(())
+}
+
+/// Creates the channel for direct messages to a user
+fn dm_channel(http: &Http, user_id: UserId) -> Result<PrivateChannel> {
+    log::debug!("Creating dm channel for user `{}`", user_id);
+    user_id
+        .create_dm_channel(http)
+        .with_context(|| format!("Failed to create dm channel for user `{}`", user_id))
+}
 
+/// Deletes or edits a reminder message in a group channel once its date has begun or ended
+fn clean_up(http: &Http, cleanup: Cleanup, nachricht: &Nachricht) {
+    let channel = ChannelId(nachricht.channel_id);
+    let result = match (cleanup, nachricht.laeuft) {
+        (Cleanup::Keep, _) | (Cleanup::Delete, true) => return,
+        (Cleanup::Delete, false) => channel.delete_message(http, nachricht.message_id),
+        (Cleanup::Edit, laeuft) => {
+            let status = Locale::default().text(if laeuft {
+                Text::LaeuftGerade
+            } else {
+                Text::Beendet
+            });
+            let content = if nachricht.inhalt.is_empty() {
+                status
+            } else {
+                format!("{}\n{}", nachricht.inhalt, status)
+            };
+            channel
+                .edit_message(http, nachricht.message_id, |m| m.content(content))
+                .map(|_| ())
+        }
+    };
+    match result {
+        Ok(_) => log::info!(
+            "Cleaned up reminder message `{}` in channel `{}`",
+            nachricht.message_id,
+            nachricht.channel_id
+        ),
+        Err(e) => log::error!("Failed to clean up reminder message: {}", e),
+    }
+}
+
+/// Sends the messages of reminders that fired at the same time for the same receiver, reminders
+/// that were missed while the bot was offline are marked as `late`
+fn send_erinnerungen(
+    http: &Http,
+    config: &Config,
+    module: &[Modul],
+    erinnerungen: &[Erinnerung<'_>],
+    late: bool,
+    protokoll: &mut Protokoll,
+) -> Result<()> {
+    let erinnerung = &erinnerungen[0];
+    // The dates of combined reminders
+    let messages = erinnerungen
+        .iter()
+        .filter_map(|v| match &v.aktion {
+            Aktion::Gruppe(_, message, ..) | Aktion::Vorher(_, message, _) => Some(message.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let ausfall = messages.iter().all(|v| v.modul_termin.ausfall);
+    // Joins the lines of the message content, adding a note to late reminders
+    let content = |mut lines: Vec<String>, locale: &Locale| {
+        if late {
+            lines.push(locale.text(Text::Verspaetet));
+        }
+        lines.join("\n")
+    };
+    match &erinnerung.aktion {
+        Aktion::Gruppe(group, ..) => {
+            let locale = Locale::default();
+            // Mentions once with the widest mention of the combined reminders
+            let mention = erinnerungen
+                .iter()
+                .filter_map(|v| match v.aktion {
+                    Aktion::Gruppe(_, _, _, mention) => Some(mention),
+                    _ => None,
+                })
+                .min()
+                .unwrap_or_default()
+                .to_content(group);
+            let mut lines = Vec::new();
+            match (mention, ausfall) {
+                (Some(mention), true) => lines.push(format!(
+                    "{} {}",
+                    mention,
+                    locale.text(Text::FaelltAus).to_lowercase()
+                )),
+                (Some(mention), false) => lines.push(mention),
+                (None, true) => lines.push(locale.text(Text::FaelltAus)),
+                (None, false) => {}
+            }
+            let lines = content(lines, &locale);
+            for (i, chunk) in messages.chunks(MAX_EMBED_FIELDS).enumerate() {
+                // Only the first message mentions the group
+                let inhalt = if i == 0 { lines.clone() } else { String::new() };
+                let message = ChannelId(group.channel_id)
+                    .send_message(http, |m| {
+                        let m = MessageData::to_create_messages(chunk, m, config, &locale);
+                        if inhalt.is_empty() {
+                            m
+                        } else {
+                            m.content(&inhalt)
+                        }
+                    })
+                    .context("Failed to send reminder message")?;
+                log::info!("Sent reminder message to channel `{}`", group.channel_id);
+                if config.reminders.cleanup != Cleanup::Keep {
+                    let termine = chunk.iter().map(|v| &v.modul_termin);
+                    let nachricht = Nachricht {
+                        channel_id: group.channel_id,
+                        message_id: message.id.0,
+                        inhalt,
+                        beginn: termine
+                            .clone()
+                            .map(|v| v.beginn.with_timezone(&Utc))
+                            .min()
+                            .unwrap_or(erinnerung.ende),
+                        ende: termine
+                            .map(|v| v.ende.with_timezone(&Utc))
+                            .max()
+                            .unwrap_or(erinnerung.ende),
+                        ausfall,
+                        laeuft: false,
+                    };
+                    protokoll.insert_nachricht(nachricht);
+                }
+            }
+        }
+        Aktion::Vorschau(kohorte, group) => {
+            let locale = Locale::default();
+            let today = erinnerung
+                .zeit
+                .with_timezone(&chrono_tz::Europe::Berlin)
+                .date();
+            let mitgliedschaft = Mitgliedschaft {
+                kohorte: kohorte.id.clone(),
+                gruppe: Some(group.gruppe()),
+                manuell: false,
+            };
+            let days = preview::days(module, &mitgliedschaft, preview::monday(today));
+            for (i, (date, messages)) in days.iter().enumerate() {
+                ChannelId(group.channel_id)
+                    .send_message(http, |m| {
+                        if i == 0 && late {
+                            m.content(content(Vec::new(), &locale));
+                        }
+                        m.embed(|e| preview::to_embed(*date, messages, config, &locale, e))
+                    })
+                    .context("Failed to send weekly preview")?;
+                log::info!("Sent weekly preview to channel `{}`", group.channel_id);
+            }
+        }
+        Aktion::Vorher(user, ..) => {
+            let channel = dm_channel(http, user.id)?;
+            let locale = user.locale();
+            let mut lines = Vec::new();
+            if ausfall {
+                lines.push(locale.text(Text::FaelltAus));
+            }
+            let lines = content(lines, &locale);
+            for (i, chunk) in messages.chunks(MAX_EMBED_FIELDS).enumerate() {
+                channel
+                    .send_message(http, |m| {
+                        let m = MessageData::to_create_messages(chunk, m, config, &locale);
+                        if i == 0 && !lines.is_empty() {
+                            m.content(&lines)
+                        } else {
+                            m
+                        }
+                    })
+                    .context("Failed to send reminder message")?;
+                log::info!("Sent reminder message to dm channel `{}`", channel.id.0);
+            }
+        }
+        Aktion::Danach(user, message) => {
+            let channel = dm_channel(http, user.id)?;
+            let locale = user.locale();
+            let duration = message
+                .modul_termin
+                .beginn
+                .signed_duration_since(Utc::now());
+            channel
+                .send_message(http, |m| {
+                    message
+                        .to_create_message(m, config, &locale)
+                        .content(content(vec![locale.relative(duration)], &locale))
+                })
+                .context("Failed to send reminder message")?;
+            log::info!("Sent reminder message to dm channel `{}`", channel.id.0);
+        }
+        Aktion::Digest(user) => {
+            let locale = user.locale();
+            let today = locale.local(&erinnerung.zeit).date();
+            let mut messages = module
+                .iter()
+                .filter(|modul| user::is_visible(&user.kohorten, Some(&user.abo), modul))
+                .flat_map(|modul| {
+                    modul.messages(|termin| locale.local(&termin.beginn).date() == today)
+                })
+                .collect::<Vec<_>>();
+            if messages.is_empty() {
+                return Ok(());
+            }
+            messages.sort_by_key(|m| m.modul_termin.beginn);
+            let channel = dm_channel(http, user.id)?;
+            for (i, chunk) in messages.chunks(MAX_EMBED_FIELDS).enumerate() {
+                channel
+                    .send_message(http, |m| {
+                        if i == 0 && late {
+                            m.content(content(Vec::new(), &locale));
+                        }
+                        m.embed(|e| digest::to_embed(chunk, config, &locale, e))
+                    })
+                    .context("Failed to send digest")?;
+                log::info!("Sent digest to dm channel `{}`", channel.id.0);
+            }
+        }
+    }
     Ok(())
 }
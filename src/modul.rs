@@ -1,65 +1,117 @@
 use crate::config::Config;
-use anyhow::{anyhow, Result};
-use chrono::{DateTime, Datelike, Weekday};
+use crate::locale::{Locale, Text};
+use chrono::{DateTime, Datelike, Utc};
 use chrono_tz::Tz;
 use derive_more::Display;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serenity::{builder::CreateMessage, utils::Color};
 
+#[derive(Clone)]
 pub struct MessageData<'m> {
     pub modul: &'m Modul,
     pub modul_termin: &'m ModulTermin,
 }
 
 impl MessageData<'_> {
+    /// Returns the identifier of the date, which is unique across modules and groups
+    pub fn key(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.modul.typ.key(),
+            self.modul.gruppe.as_ref().map_or("", |v| v.0.as_str()),
+            self.modul_termin.beginn.with_timezone(&Utc).to_rfc3339()
+        )
+    }
+
+    /// Fills a message with several dates that begin at the same time, a single date is shown
+    /// in full
+    ///
+    /// The embed takes one field per date, so callers split larger batches into chunks. It is
+    /// only colored if all dates share the color of their module.
+    pub fn to_create_messages<'a, 'b>(
+        messages: &[Self],
+        msg: &'b mut CreateMessage<'a>,
+        cfg: &Config,
+        locale: &Locale,
+    ) -> &'b mut CreateMessage<'a> {
+        let first = match messages {
+            [message] => return message.to_create_message(msg, cfg, locale),
+            [first, ..] => first,
+            [] => return msg,
+        };
+        msg.embed(|embed| {
+            let beginn = locale.local(&first.modul_termin.beginn).format("%H:%M");
+            embed.title(locale.text(Text::TermineUm(&beginn.to_string())));
+            let color = first.modul.embed_color(cfg);
+            if messages.iter().all(|v| v.modul.embed_color(cfg) == color) {
+                embed.color(color);
+            }
+            for message in messages {
+                embed.field(
+                    message.modul.title(),
+                    crate::digest::describe(message, cfg, locale),
+                    false,
+                );
+            }
+            embed
+        })
+    }
+
     pub fn to_create_message<'a, 'b>(
         &self,
         msg: &'b mut CreateMessage<'a>,
         cfg: &Config,
+        locale: &Locale,
     ) -> &'b mut CreateMessage<'a> {
         msg.embed(|mut embed| {
             embed = embed
                 .title(self.modul.title())
-                .color(self.modul.embed_color())
-                .description(format!(
-                    "{} {} - {}",
-                    match self.modul_termin.beginn.weekday() {
-                        Weekday::Mon => "Montag",
-                        Weekday::Tue => "Dienstag",
-                        Weekday::Wed => "Mittwoch",
-                        Weekday::Thu => "Donnerstag",
-                        Weekday::Fri => "Freitag",
-                        Weekday::Sat => "Samstag",
-                        Weekday::Sun => "Sonntag",
-                    },
-                    self.modul_termin.beginn.format("%H:%M"),
-                    self.modul_termin.ende.format("%H:%M")
-                ));
+                .color(self.modul.embed_color(cfg))
+                .description(self.modul_termin.description(locale));
             if let Some(online_link) = self.modul.online_link(cfg) {
-                embed = embed.field("Online", online_link, false);
+                embed = embed.field(locale.text(Text::Online), online_link, false);
+            }
+            if let Some(art) = &self.modul.art {
+                embed = embed.field(locale.text(Text::Art), art, true);
+            }
+            if let Some(dozent) = &self.modul.dozent {
+                embed = embed.field(locale.text(Text::Dozent), dozent, true);
             }
             if let Some(raum) = &self.modul.raum {
-                embed = embed.field("Raum", raum, false);
+                embed = embed.field(locale.text(Text::Raum), raum, false);
+            }
+            if let Some(rhythmus) = &self.modul.rhythmus {
+                embed = embed.field(locale.text(Text::Rhythmus), rhythmus, true);
+            }
+            if let Some(dauer) = &self.modul.dauer {
+                embed = embed.field(locale.text(Text::Dauer), dauer, true);
             }
             if let Some(bemerkung) = &self.modul.bemerkung {
-                embed = embed.field("Bemerkung", bemerkung, false);
+                embed = embed.field(locale.text(Text::Bemerkung), bemerkung, false);
             }
             embed
         })
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Modul {
     pub typ: ModulTyp,
     pub gruppe: Option<ModulGruppe>,
     pub termine: Vec<ModulTermin>,
     pub raum: Option<String>,
     pub bemerkung: Option<String>,
+    /// Veranstaltungsart, e.g. `Vorlesung` or `Übung`
+    pub art: Option<String>,
+    pub dozent: Option<String>,
+    /// Rhythmus, e.g. `wöch` or `Einzel`
+    pub rhythmus: Option<String>,
+    /// Date range of the entry, e.g. `05.10.2020 bis 19.10.2020`
+    pub dauer: Option<String>,
 }
 
 impl Modul {
-    pub fn messages<F>(&self, filter: F) -> Vec<MessageData>
+    pub fn messages<F>(&self, filter: F) -> Vec<MessageData<'_>>
     where
         F: Fn(&ModulTermin) -> bool,
     {
@@ -73,86 +125,168 @@ impl Modul {
             .collect()
     }
 
-    fn title(&self) -> String {
+    pub fn title(&self) -> String {
         match &self.gruppe {
             Some(gruppe) => format!("{} ({})", self.typ, gruppe),
             None => self.typ.to_string(),
         }
     }
 
-    fn online_link(&self, cfg: &Config) -> Option<String> {
-        let link_data = match self.typ {
-            ModulTyp::Mathematik1 => &cfg.links.mathematik1,
-            ModulTyp::Programmiertechnik1 => &cfg.links.programmiertechnik1,
-            ModulTyp::Softwaremodellierung => &cfg.links.softwaremodellierung,
-            ModulTyp::Digitaltechnik => &cfg.links.digitaltechnik,
-        };
-        match self.gruppe {
-            Some(_) => link_data.uebungen.clone(),
-            None => link_data.vorlesungen.clone(),
+    /// Returns whether the dates are exercises instead of lectures
+    ///
+    /// Dates without a type or with a combined type like `Vorlesung/Übung` are exercises if they
+    /// are held in groups.
+    pub fn is_uebung(&self) -> bool {
+        let art = self.art.as_deref().unwrap_or_default().to_lowercase();
+        match (art.contains("übung"), art.contains("vorlesung")) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => self.gruppe.is_some(),
         }
     }
 
-    fn embed_color(&self) -> Color {
-        match self.typ {
-            ModulTyp::Mathematik1 => Color::BLUE,
-            ModulTyp::Programmiertechnik1 => Color::ORANGE,
-            ModulTyp::Softwaremodellierung => Color::PURPLE,
-            ModulTyp::Digitaltechnik => Color::DARK_GREEN,
+    pub fn online_link(&self, cfg: &Config) -> Option<String> {
+        let modul = cfg.modul(&self.typ)?;
+        if self.is_uebung() {
+            modul.uebungen.clone()
+        } else {
+            modul.vorlesungen.clone()
         }
     }
+
+    fn embed_color(&self, cfg: &Config) -> Color {
+        cfg.modul(&self.typ)
+            .and_then(|modul| modul.color)
+            .map(Color::new)
+            .unwrap_or_default()
+    }
 }
 
-#[derive(Clone, Debug, Display, Eq, PartialEq)]
-pub enum ModulTyp {
-    #[display(fmt = "Mathematik 1")]
-    Mathematik1,
-    #[display(fmt = "Programmiertechnik 1")]
-    Programmiertechnik1,
-    #[display(fmt = "Softwaremodellierung")]
-    Softwaremodellierung,
-    #[display(fmt = "Digitaltechnik")]
-    Digitaltechnik,
+/// Identifies a module from the config
+#[derive(Clone, Debug, Display, Eq, PartialEq, Deserialize, Serialize)]
+#[display(fmt = "{}", name)]
+pub struct ModulTyp {
+    /// Identifier of the cohort of the module
+    pub kohorte: String,
+    pub id: String,
+    pub name: String,
 }
 
 impl ModulTyp {
-    pub fn parse(input: &str) -> Result<Self> {
-        match input {
-            "AIN1 Mathematik 1" => Ok(Self::Mathematik1),
-            "AIN1 Programmiertechnik1 - findet online statt" => Ok(Self::Programmiertechnik1),
-            "AIN1 Softwaremodellierung" => Ok(Self::Softwaremodellierung),
-            "AIN1 Digitaltechnik" => Ok(Self::Digitaltechnik),
-            _ => Err(anyhow!("Unknown name `{}`", input)),
+    pub fn new(kohorte: &str, id: &str, name: &str) -> Self {
+        Self {
+            kohorte: kohorte.to_owned(),
+            id: id.to_owned(),
+            name: name.to_owned(),
         }
     }
-}
 
-#[derive(Clone, Debug, Display, Eq, PartialEq, Deserialize, Serialize)]
-pub enum ModulGruppe {
-    #[display(fmt = "Gruppe 1")]
-    Gruppe1,
-    #[display(fmt = "Gruppe 2")]
-    Gruppe2,
-    #[display(fmt = "Gruppe 3")]
-    Gruppe3,
-    #[display(fmt = "Gruppe 4")]
-    Gruppe4,
+    /// Returns the identifier that is unique across cohorts (e.g. `ain1/digitaltechnik`)
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.kohorte, self.id)
+    }
 }
 
+/// Name of a group as shown in LSF (e.g. `Gruppe 1`)
+#[derive(Clone, Debug, Display, Eq, PartialEq, Serialize)]
+#[display(fmt = "{}", _0)]
+#[serde(transparent)]
+pub struct ModulGruppe(pub String);
+
 impl ModulGruppe {
-    pub fn parse(input: &str) -> Result<Self> {
-        match input {
-            "Gruppe 1" => Ok(Self::Gruppe1),
-            "Gruppe 2" => Ok(Self::Gruppe2),
-            "Gruppe 3" => Ok(Self::Gruppe3),
-            "Gruppe 4" => Ok(Self::Gruppe4),
-            _ => Err(anyhow!("Unknown group `{}`", input)),
+    pub fn new(name: &str) -> Self {
+        Self(name.to_owned())
+    }
+}
+
+impl<'de> Deserialize<'de> for ModulGruppe {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        // Files written before groups were configurable contain `Gruppe1` to `Gruppe4`
+        match name.strip_prefix("Gruppe") {
+            Some(n) if n.len() == 1 && n.chars().all(|c| c.is_ascii_digit()) => {
+                Ok(Self(format!("Gruppe {}", n)))
+            }
+            _ => Ok(Self(name)),
         }
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct ModulTermin {
+    #[serde(with = "berlin")]
     pub beginn: DateTime<Tz>,
+    #[serde(with = "berlin")]
     pub ende: DateTime<Tz>,
+    /// Whether the date is cancelled (`fällt aus`)
+    #[serde(default)]
+    pub ausfall: bool,
+}
+
+impl ModulTermin {
+    /// Formats the weekday and time, struck through if the date is cancelled
+    pub fn description(&self, locale: &Locale) -> String {
+        let beginn = locale.local(&self.beginn);
+        let description = format!(
+            "{} {} - {}",
+            locale.wochentag(beginn.weekday()),
+            beginn.format("%H:%M"),
+            locale.local(&self.ende).format("%H:%M")
+        );
+        if self.ausfall {
+            format!(
+                "~~{}~~ ({})",
+                description,
+                locale.text(Text::FaelltAus).to_lowercase()
+            )
+        } else {
+            description
+        }
+    }
+}
+
+/// (De)serializes a date as RFC 3339 and converts it back to the Europe/Berlin time zone
+mod berlin {
+    use chrono::{DateTime, FixedOffset};
+    use chrono_tz::Tz;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &DateTime<Tz>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.to_rfc3339().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Tz>, D::Error> {
+        let value = DateTime::<FixedOffset>::deserialize(deserializer)?;
+        Ok(value.with_timezone(&chrono_tz::Europe::Berlin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_gruppe() {
+        let gruppe: ModulGruppe = serde_json::from_str("\"Gruppe3\"").unwrap();
+        assert_eq!(gruppe, ModulGruppe::new("Gruppe 3"));
+        let gruppe: ModulGruppe = serde_json::from_str("\"Gruppe A\"").unwrap();
+        assert_eq!(gruppe, ModulGruppe::new("Gruppe A"));
+    }
+
+    #[test]
+    fn uebung() {
+        let modul = |art: Option<&str>, gruppe| Modul {
+            art: art.map(str::to_owned),
+            ..crate::fixture::modul("digitaltechnik", gruppe, Vec::new())
+        };
+        assert!(modul(Some("Übung"), None).is_uebung());
+        assert!(!modul(Some("Vorlesung"), Some("Gruppe 1")).is_uebung());
+        assert!(modul(Some("Vorlesung/Übung"), Some("Gruppe 1")).is_uebung());
+        assert!(!modul(None, None).is_uebung());
+    }
 }
@@ -0,0 +1,152 @@
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Returns the path with `suffix` appended to the file name (e.g. `users.toml.lock`)
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+/// Returns the path of the `n`th backup of a file, `1` being the most recent one
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    with_suffix(path, &n.to_string())
+}
+
+/// Advisory lock on a file, which is released when dropped
+///
+/// The lock is held on a separate `.lock` file, because the file itself is replaced on every write.
+/// Manual edits can take the same lock, e.g. with `flock users.toml.lock vim users.toml`.
+pub struct Lock(File);
+
+impl Lock {
+    pub fn shared(path: &Path) -> Result<Self> {
+        let file = Self::open(path)?;
+        file.lock_shared().context("Failed to lock file")?;
+        Ok(Self(file))
+    }
+
+    pub fn exclusive(path: &Path) -> Result<Self> {
+        let file = Self::open(path)?;
+        file.lock_exclusive().context("Failed to lock file")?;
+        Ok(Self(file))
+    }
+
+    fn open(path: &Path) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(with_suffix(path, "lock"))
+            .context("Failed to open lock file")
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+/// Reads a file while holding a shared lock
+pub fn read<P: AsRef<Path>>(path: P) -> Result<String> {
+    let path = path.as_ref();
+    let _lock = Lock::shared(path)?;
+    fs::read_to_string(path).with_context(|| format!("Failed to read `{}`", path.display()))
+}
+
+/// Replaces the contents of a file atomically while holding an exclusive lock
+///
+/// The contents are written to a temporary file, synced to disk and renamed over the original file,
+/// so a crash never leaves a partially written file behind. The previous `backups` versions are
+/// kept as `<file>.1` (most recent) to `<file>.<backups>`.
+pub fn write<P: AsRef<Path>>(path: P, contents: &str, backups: usize) -> Result<()> {
+    let path = path.as_ref();
+    let _lock = Lock::exclusive(path)?;
+    replace(path, contents, backups)
+}
+
+/// Reads a file, passes its contents to `f` and writes the result, all while holding an exclusive
+/// lock, so changes made to the file in the meantime are not lost
+pub fn update<P, F>(path: P, backups: usize, f: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(String) -> Result<String>,
+{
+    let path = path.as_ref();
+    let _lock = Lock::exclusive(path)?;
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    replace(path, &f(contents)?, backups)
+}
+
+fn replace(path: &Path, contents: &str, backups: usize) -> Result<()> {
+    if backups > 0 && path.exists() {
+        for n in (1..backups).rev() {
+            let from = backup_path(path, n);
+            if from.exists() {
+                fs::rename(&from, backup_path(path, n + 1)).context("Failed to rotate backups")?;
+            }
+        }
+        fs::copy(path, backup_path(path, 1)).context("Failed to create backup")?;
+    }
+
+    let temp_path = with_suffix(path, "tmp");
+    let mut file = File::create(&temp_path).context("Failed to create temporary file")?;
+    file.write_all(contents.as_bytes())
+        .and_then(|_| file.sync_all())
+        .context("Failed to write temporary file")?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to replace `{}`", path.display()))?;
+    if let Some(dir) = path.parent().filter(|v| !v.as_os_str().is_empty()) {
+        // Persists the rename, opening directories is not supported on every platform
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::temp_dir;
+
+    #[test]
+    fn shorter_contents() {
+        let path = temp_dir("shorter").join("users.toml");
+        write(&path, "a long first version", 0).unwrap();
+        write(&path, "short", 0).unwrap();
+        assert_eq!(read(&path).unwrap(), "short");
+        assert!(!backup_path(&path, 1).exists());
+    }
+
+    #[test]
+    fn rotate_backups() {
+        let path = temp_dir("backups").join("users.toml");
+        for i in 1..=4 {
+            write(&path, &i.to_string(), 2).unwrap();
+        }
+        assert_eq!(read(&path).unwrap(), "4");
+        assert_eq!(fs::read_to_string(backup_path(&path, 1)).unwrap(), "3");
+        assert_eq!(fs::read_to_string(backup_path(&path, 2)).unwrap(), "2");
+        assert!(!backup_path(&path, 3).exists());
+    }
+
+    #[test]
+    fn update_current_contents() {
+        let path = temp_dir("update").join("users.toml");
+        write(&path, "1", 1).unwrap();
+        fs::write(&path, "2").unwrap();
+        update(&path, 1, |v| Ok(format!("{}3", v))).unwrap();
+        assert_eq!(read(&path).unwrap(), "23");
+        assert_eq!(fs::read_to_string(backup_path(&path, 1)).unwrap(), "2");
+        assert!(update(&path, 1, |_| Err(anyhow::anyhow!("failed"))).is_err());
+        assert_eq!(read(&path).unwrap(), "23");
+    }
+}
@@ -0,0 +1,184 @@
+use crate::config::{Config, Preview};
+use crate::digest;
+use crate::locale::{Locale, Text};
+use crate::modul::{MessageData, Modul};
+use crate::user::{self, Mitgliedschaft};
+use chrono::{Date, DateTime, Datelike, Duration, Utc, Weekday};
+use chrono_tz::Tz;
+use serenity::{builder::CreateEmbed, utils::Color};
+
+/// Returns the first time after `after` at which the weekly preview is due
+pub fn next(preview: &Preview, after: DateTime<Utc>) -> DateTime<Utc> {
+    digest::next_on(preview.time(), chrono_tz::Europe::Berlin, after, |date| {
+        date.weekday() == preview.weekday
+    })
+}
+
+/// Returns the Monday of the week that is previewed on `today`, which is `today` itself on a Monday
+/// and the next Monday otherwise
+pub fn monday(today: Date<Tz>) -> Date<Tz> {
+    let days = (7 - today.weekday().num_days_from_monday()) % 7;
+    today + Duration::days(days as i64)
+}
+
+/// Returns the dates of a group for every day of the week starting on `monday`
+///
+/// Saturday and Sunday are only included if there are dates on them.
+pub fn days<'m>(
+    module: &'m [Modul],
+    mitgliedschaft: &Mitgliedschaft,
+    monday: Date<Tz>,
+) -> Vec<(Date<Tz>, Vec<MessageData<'m>>)> {
+    (0..7)
+        .map(|i| monday + Duration::days(i))
+        .map(|date| {
+            let mut messages = module
+                .iter()
+                .filter(|modul| user::affects(std::slice::from_ref(mitgliedschaft), modul))
+                .flat_map(|modul| modul.messages(|termin| termin.beginn.date() == date))
+                .collect::<Vec<_>>();
+            messages.sort_by_key(|m| m.modul_termin.beginn);
+            (date, messages)
+        })
+        .filter(|(date, messages)| {
+            !messages.is_empty() || !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+        })
+        .collect()
+}
+
+/// Fills an embed with the dates of a single day of the preview
+pub fn to_embed<'a>(
+    date: Date<Tz>,
+    messages: &[MessageData<'_>],
+    cfg: &Config,
+    locale: &Locale,
+    embed: &'a mut CreateEmbed,
+) -> &'a mut CreateEmbed {
+    embed
+        .title(format!(
+            "{}, {}",
+            locale.wochentag(date.weekday()),
+            locale.format_date(date.naive_local())
+        ))
+        .color(Color::DARK_GREEN);
+    if messages.is_empty() {
+        embed.description(locale.text(Text::KeineVeranstaltungen));
+    }
+    for message in messages {
+        embed.field(message.modul.title(), describe(message, cfg, locale), false);
+    }
+    embed
+}
+
+fn describe(message: &MessageData<'_>, cfg: &Config, locale: &Locale) -> String {
+    let termin = message.modul_termin;
+    let mut string = format!(
+        "{} - {}",
+        locale.local(&termin.beginn).format("%H:%M"),
+        locale.local(&termin.ende).format("%H:%M")
+    );
+    if let Some(raum) = &message.modul.raum {
+        string.push_str(&format!(", {} {}", locale.text(Text::Raum), raum));
+    }
+    if let Some(online_link) = message.modul.online_link(cfg) {
+        string.push_str(&format!("\n{}", online_link));
+    }
+    if let Some(bemerkung) = message.modul.bemerkung.as_ref().filter(|v| !v.is_empty()) {
+        string.push_str(&format!(
+            "\n{}: {}",
+            locale.text(Text::Bemerkung),
+            bemerkung
+        ));
+    }
+    if let Some(abweichung) = abweichung(message, locale) {
+        string.push_str(&format!("\n**{}**", abweichung));
+    }
+    string
+}
+
+/// Describes how a date differs from the usual weekly rhythm of its module
+fn abweichung(message: &MessageData<'_>, locale: &Locale) -> Option<String> {
+    if message.modul_termin.ausfall {
+        return Some(locale.text(Text::FaelltAus));
+    }
+    match &message.modul.rhythmus {
+        Some(rhythmus) if !rhythmus.starts_with("wöch") => {
+            Some(locale.text(Text::AbweichenderRhythmus(rhythmus)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::modul::ModulGruppe;
+    use chrono::TimeZone;
+
+    fn modul(gruppe: Option<&str>, rhythmus: &str, days: &[u32]) -> Modul {
+        let termine = days
+            .iter()
+            .map(|day| fixture::termin(*day, (8, 0), (9, 30)))
+            .collect();
+        Modul {
+            rhythmus: Some(rhythmus.to_owned()),
+            ..fixture::modul("digitaltechnik", gruppe, termine)
+        }
+    }
+
+    #[test]
+    fn monday_of_week() {
+        let date = |day| chrono_tz::Europe::Berlin.ymd(2020, 10, day);
+        assert_eq!(monday(date(11)), date(12));
+        assert_eq!(monday(date(12)), date(12));
+        assert_eq!(monday(date(14)), date(19));
+    }
+
+    #[test]
+    fn next_preview() {
+        let preview = Preview {
+            weekday: Weekday::Sun,
+            time: None,
+        };
+        let berlin = |day, hour| {
+            chrono_tz::Europe::Berlin
+                .ymd(2020, 10, day)
+                .and_hms(hour, 0, 0)
+                .with_timezone(&Utc)
+        };
+        assert_eq!(next(&preview, berlin(5, 12)), berlin(11, 18));
+        assert_eq!(next(&preview, berlin(11, 18)), berlin(18, 18));
+    }
+
+    #[test]
+    fn days_of_group() {
+        let module = vec![
+            modul(None, "wöch", &[12, 19]),
+            modul(Some("Gruppe 1"), "wöch", &[13]),
+            modul(Some("Gruppe 2"), "wöch", &[14]),
+            modul(None, "Einzel", &[17]),
+        ];
+        let mitgliedschaft = Mitgliedschaft {
+            kohorte: "ain1".to_owned(),
+            gruppe: Some(ModulGruppe::new("Gruppe 1")),
+            manuell: false,
+        };
+        let monday = chrono_tz::Europe::Berlin.ymd(2020, 10, 12);
+        let days = days(&module, &mitgliedschaft, monday);
+        let counts = days
+            .iter()
+            .map(|(date, messages)| (date.day(), messages.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![(12, 1), (13, 1), (14, 0), (15, 0), (16, 0), (17, 1)]
+        );
+        let locale = Locale::default();
+        assert_eq!(abweichung(&days[0].1[0], &locale), None);
+        assert_eq!(
+            abweichung(&days[5].1[0], &locale).as_deref(),
+            Some("Abweichender Rhythmus: Einzel")
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use crate::config::{Config, Refresh};
+use crate::{source, Data};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use serenity::http::Http;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Outcome of the last schedule refreshes
+#[derive(Default)]
+pub struct Status {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<(DateTime<Utc>, String)>,
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+impl Status {
+    pub fn record_success(&mut self) {
+        let now = Utc::now();
+        log::info!("Refreshed schedule at {}", now);
+        self.last_success = Some(now);
+    }
+
+    pub fn record_failure(&mut self, error: &anyhow::Error) {
+        let now = Utc::now();
+        log::error!("Failed to refresh schedule at {}: {:#}", now, error);
+        self.last_failure = Some((now, format!("{:#}", error)));
+    }
+}
+
+/// Returns the time of the next regular refresh after `now`
+pub fn next_run(refresh: &Refresh, now: DateTime<Utc>) -> DateTime<Utc> {
+    let next = now + Duration::minutes(refresh.interval as i64);
+    let nightly = match refresh.nightly {
+        Some(v) => v,
+        None => return next,
+    };
+    let mut date = now
+        .with_timezone(&chrono_tz::Europe::Berlin)
+        .naive_local()
+        .date();
+    loop {
+        if let Some(candidate) = chrono_tz::Europe::Berlin
+            .from_local_datetime(&NaiveDateTime::new(date, nightly))
+            .earliest()
+            .map(|v| v.with_timezone(&Utc))
+            .filter(|v| *v > now)
+        {
+            return next.min(candidate);
+        }
+        date = date.succ();
+    }
+}
+
+/// Returns the time to wait after the given number of consecutive failures
+pub fn backoff(refresh: &Refresh, failures: u32) -> Duration {
+    let max = refresh.max_retry.unwrap_or(refresh.interval);
+    let factor = 2u64.saturating_pow(failures.saturating_sub(1));
+    Duration::minutes(refresh.retry.saturating_mul(factor).min(max) as i64)
+}
+
+/// Fetches the schedule periodically as configured in the `refresh` section
+///
+/// The `Data` mutex is only locked to swap in the new schedule, so fetching never blocks the
+/// reminder loop.
+pub fn run(config: Arc<Config>, data: Arc<Mutex<Data>>, http: Arc<Http>) {
+    let refresh = match &config.refresh {
+        Some(v) => v,
+        None => {
+            log::debug!("Automatic refresh of the schedule is disabled");
+            return;
+        }
+    };
+    let mut failures = 0;
+    let mut next = next_run(refresh, Utc::now());
+    loop {
+        data.lock().unwrap().refresh.next_run = Some(next);
+        log::debug!("Next refresh of the schedule at {}", next);
+        if let Ok(duration) = next.signed_duration_since(Utc::now()).to_std() {
+            thread::sleep(duration);
+        }
+
+        log::debug!("Refreshing schedule");
+        match source::fetch_module(&config) {
+            Ok(module) => {
+                failures = 0;
+                crate::apply_schedule(&http, &config, &data, module);
+                next = next_run(refresh, Utc::now());
+            }
+            Err(e) => {
+                failures += 1;
+                data.lock().unwrap().refresh.record_failure(&e);
+                next = Utc::now() + backoff(refresh, failures);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    fn refresh(nightly: Option<(u32, u32)>) -> Refresh {
+        Refresh {
+            interval: 6 * 60,
+            nightly: nightly.map(|(h, m)| NaiveTime::from_hms(h, m, 0)),
+            retry: 5,
+            max_retry: Some(60),
+        }
+    }
+
+    fn berlin(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        chrono_tz::Europe::Berlin
+            .ymd(2020, 10, day)
+            .and_hms(hour, minute, 0)
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn interval() {
+        assert_eq!(next_run(&refresh(None), berlin(5, 12, 0)), berlin(5, 18, 0));
+    }
+
+    #[test]
+    fn nightly_before_interval() {
+        assert_eq!(
+            next_run(&refresh(Some((3, 0))), berlin(5, 23, 0)),
+            berlin(6, 3, 0)
+        );
+    }
+
+    #[test]
+    fn interval_before_nightly() {
+        assert_eq!(
+            next_run(&refresh(Some((3, 0))), berlin(5, 12, 0)),
+            berlin(5, 18, 0)
+        );
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let refresh = refresh(None);
+        let minutes = (1..=6)
+            .map(|failures| backoff(&refresh, failures).num_minutes())
+            .collect::<Vec<_>>();
+        assert_eq!(minutes, vec![5, 10, 20, 40, 60, 60]);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::config::Kohorte;
+use crate::modul::ModulGruppe;
+use anyhow::{Context, Result};
+use serenity::http::Http;
+use serenity::model::{guild::Member, id::UserId, permissions::Permissions};
+use std::collections::HashMap;
+
+/// Reason why the bot may not assign the group roles of a cohort
+pub enum Fehlend {
+    /// The bot lacks the Manage Roles permission
+    ManageRoles,
+    /// The role with this name is not below the highest role of the bot
+    Position(String),
+}
+
+/// Checks whether the bot may assign the group roles of a cohort, which requires the Manage Roles
+/// permission and a role above each of them
+pub fn missing_permission(
+    http: &Http,
+    guild_id: u64,
+    kohorte: &Kohorte,
+) -> Result<Option<Fehlend>> {
+    let bot_id = http
+        .get_current_user()
+        .context("Failed to fetch bot user")?
+        .id;
+    let member = http
+        .get_member(guild_id, bot_id.0)
+        .context("Failed to fetch bot member")?;
+    let roles = http
+        .get_guild_roles(guild_id)
+        .context("Failed to fetch guild roles")?;
+    // The `@everyone` role has the id of the guild
+    let bot_roles = roles
+        .iter()
+        .filter(|role| role.id.0 == guild_id || member.roles.contains(&role.id))
+        .collect::<Vec<_>>();
+    let permissions = bot_roles
+        .iter()
+        .fold(Permissions::empty(), |acc, role| acc | role.permissions);
+    if !permissions.manage_roles() && !permissions.administrator() {
+        return Ok(Some(Fehlend::ManageRoles));
+    }
+    let highest = bot_roles.iter().map(|role| role.position).max();
+    let blocked = roles.iter().find(|role| {
+        kohorte
+            .groups
+            .iter()
+            .any(|group| group.role_id == role.id.0)
+            && highest.is_none_or(|v| role.position >= v)
+    });
+    Ok(blocked.map(|role| Fehlend::Position(role.name.clone())))
+}
+
+/// Cache of the Discord roles of the members of the guild
+///
+/// The cache is filled from the gateway events of the guild and with [`fetch`] for members that
+/// are missing.
+#[derive(Default)]
+pub struct Rollen(HashMap<UserId, Vec<u64>>);
+
+impl Rollen {
+    /// Stores the roles of a member and returns whether they changed
+    pub fn insert(&mut self, member: &Member) -> bool {
+        let roles = member.roles.iter().map(|role| role.0).collect::<Vec<_>>();
+        self.0.insert(member.user.read().id, roles.clone()) != Some(roles)
+    }
+
+    pub fn remove(&mut self, user_id: UserId) {
+        self.0.remove(&user_id);
+    }
+
+    pub fn get(&self, user_id: UserId) -> Option<&[u64]> {
+        self.0.get(&user_id).map(Vec::as_slice)
+    }
+}
+
+/// Fetches a member of the guild whose roles are not cached yet
+pub fn fetch(http: &Http, guild_id: u64, user_id: UserId) -> Result<Member> {
+    http.get_member(guild_id, user_id.0)
+        .context("Failed to fetch guild member")
+}
+
+/// Adds the role of `gruppe` to a member with the current `roles` and removes the roles of the
+/// other groups of the cohort
+///
+/// The cache is updated by the resulting member update event.
+pub fn assign_group(
+    http: &Http,
+    guild_id: u64,
+    user_id: UserId,
+    roles: &[u64],
+    kohorte: &Kohorte,
+    gruppe: Option<&ModulGruppe>,
+) -> Result<()> {
+    for group in &kohorte.groups {
+        let assign = gruppe.is_some_and(|v| group.name == v.0);
+        if assign && !roles.contains(&group.role_id) {
+            http.add_member_role(guild_id, user_id.0, group.role_id)
+                .with_context(|| format!("Failed to add role `{}`", group.role_id))?;
+        } else if !assign && roles.contains(&group.role_id) {
+            http.remove_member_role(guild_id, user_id.0, group.role_id)
+                .with_context(|| format!("Failed to remove role `{}`", group.role_id))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,514 @@
+use crate::config::{Config, Group, Kohorte, Mention};
+use crate::modul::{MessageData, Modul};
+use crate::user::User;
+use crate::zustellung::Zustellung;
+use crate::{digest, preview};
+use chrono::{DateTime, Duration, Utc};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// What is sent when a reminder fires
+pub enum Aktion<'m> {
+    /// Reminder of a date in the channel of one of its groups, sent the given number of minutes
+    /// before its beginning
+    Gruppe(&'m Group, MessageData<'m>, u64, Mention),
+    /// Weekly preview in the channel of a group
+    Vorschau(&'m Kohorte, &'m Group),
+    /// Reminder of a date sent the given number of minutes before its beginning (`send_before`)
+    Vorher(&'m User, MessageData<'m>, u64),
+    /// Reminder of the next date sent at the end of the previous one (`send_after_previous`)
+    Danach(&'m User, MessageData<'m>),
+    /// Daily digest
+    Digest(&'m User),
+}
+
+/// A reminder that fires at `zeit`
+pub struct Erinnerung<'m> {
+    pub zeit: DateTime<Utc>,
+    /// Time after which the reminder is obsolete, e.g. the end of its date
+    pub ende: DateTime<Utc>,
+    pub aktion: Aktion<'m>,
+}
+
+impl Erinnerung<'_> {
+    /// Returns the key of the reminder in the delivery log
+    pub fn zustellung(&self) -> Zustellung {
+        let day = |tz: chrono_tz::Tz| self.zeit.with_timezone(&tz).format("%Y-%m-%d").to_string();
+        let (ziel, termin, offset) = match &self.aktion {
+            Aktion::Gruppe(group, message, minutes, _) => (
+                format!("channel/{}", group.channel_id),
+                message.key(),
+                minutes.to_string(),
+            ),
+            Aktion::Vorschau(_, group) => (
+                format!("channel/{}", group.channel_id),
+                day(chrono_tz::Europe::Berlin),
+                "preview".to_owned(),
+            ),
+            Aktion::Vorher(user, message, minutes) => (
+                format!("user/{}", user.id),
+                message.key(),
+                minutes.to_string(),
+            ),
+            Aktion::Danach(user, message) => (
+                format!("user/{}", user.id),
+                message.key(),
+                "after".to_owned(),
+            ),
+            Aktion::Digest(user) => (
+                format!("user/{}", user.id),
+                day(user.locale().zeitzone),
+                "digest".to_owned(),
+            ),
+        };
+        Zustellung {
+            ziel,
+            termin,
+            offset,
+        }
+    }
+
+    /// Returns the receiver, the time and the beginning of the dates of reminders that are
+    /// combined into a single message
+    fn batch_key(&self) -> Option<(String, DateTime<Utc>, DateTime<Utc>)> {
+        match &self.aktion {
+            Aktion::Gruppe(_, message, ..) | Aktion::Vorher(_, message, _) => Some((
+                self.zustellung().ziel,
+                self.zeit,
+                message.modul_termin.beginn.with_timezone(&Utc),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a reminder is still useful at `now`, which is the case until its date
+    /// begins, or until it ends for reminders sent at its beginning
+    pub fn is_current(&self, now: DateTime<Utc>) -> bool {
+        match &self.aktion {
+            Aktion::Gruppe(_, _, 0, _) | Aktion::Vorher(_, _, 0) => now < self.ende,
+            Aktion::Gruppe(_, message, ..)
+            | Aktion::Vorher(_, message, _)
+            | Aktion::Danach(_, message) => message.modul_termin.beginn > now,
+            Aktion::Vorschau(..) | Aktion::Digest(_) => now < self.ende,
+        }
+    }
+}
+
+impl PartialEq for Erinnerung<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.zeit == other.zeit
+    }
+}
+
+impl Eq for Erinnerung<'_> {}
+
+impl PartialOrd for Erinnerung<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Erinnerung<'_> {
+    /// Reminders are ordered by the time at which they fire
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.zeit.cmp(&other.zeit)
+    }
+}
+
+/// Queue of all reminders after a point in time, ordered by the time at which they fire
+///
+/// The queue is computed from the schedule and the settings of the users, so it is rebuilt
+/// whenever one of them changes. Reminders up to `after` are never included again, which
+/// guarantees that each reminder fires exactly once even if the queue is rebuilt in between.
+/// Every digest and preview up to `now` is included, so one that keeps failing does not hold
+/// back the following ones.
+pub struct Zeitplan<'m> {
+    queue: BinaryHeap<Reverse<Erinnerung<'m>>>,
+}
+
+impl<'m> Zeitplan<'m> {
+    pub fn new(
+        config: &'m Config,
+        module: &'m [Modul],
+        users: &'m [User],
+        after: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let mut queue = BinaryHeap::new();
+        let mut push = |zeit: DateTime<Utc>, ende: DateTime<Utc>, aktion| {
+            if zeit > after {
+                queue.push(Reverse(Erinnerung { zeit, ende, aktion }));
+            }
+        };
+        let notify = |message: &MessageData<'_>| {
+            !message.modul_termin.ausfall || config.discord.notify_ausfall
+        };
+        let messages = module
+            .iter()
+            .flat_map(|modul| modul.messages(|_| true))
+            .collect::<Vec<_>>();
+
+        // Beginning of the first date of each day in each group channel
+        let mut first = HashMap::new();
+        for message in messages.iter().filter(|v| notify(v)) {
+            let beginn = message.modul_termin.beginn;
+            for group in config.groups_for(&message.modul.typ, &message.modul.gruppe) {
+                first
+                    .entry((group.channel_id, beginn.date()))
+                    .and_modify(|v| *v = beginn.min(*v))
+                    .or_insert(beginn);
+            }
+        }
+        for message in messages.iter().filter(|v| notify(v)) {
+            let beginn = message.modul_termin.beginn;
+            let ende = message.modul_termin.ende.with_timezone(&Utc);
+            for group in config.groups_for(&message.modul.typ, &message.modul.gruppe) {
+                let settings = config.channel_settings(&message.modul.typ, group);
+                let mention = if settings.mention_first_only
+                    && first.get(&(group.channel_id, beginn.date())) != Some(&beginn)
+                {
+                    Mention::None
+                } else {
+                    settings.mention
+                };
+                for minutes in settings.send_before {
+                    push(
+                        beginn.with_timezone(&Utc) - Duration::minutes(minutes as i64),
+                        ende,
+                        Aktion::Gruppe(group, message.clone(), minutes, mention),
+                    );
+                }
+            }
+        }
+
+        if let Some(v) = &config.preview {
+            for zeit in occurrences(after, now, |after| preview::next(v, after)) {
+                for kohorte in &config.kohorten {
+                    for group in &kohorte.groups {
+                        push(
+                            zeit,
+                            zeit + Duration::days(1),
+                            Aktion::Vorschau(kohorte, group),
+                        );
+                    }
+                }
+            }
+        }
+
+        for user in users.iter().filter(|user| user.enabled) {
+            // Quiet hours and pauses apply to the time at which a reminder fires
+            let allowed = |zeit: DateTime<Utc>| !user.is_paused(zeit) && !user.is_quiet(zeit);
+            let subscribed = messages
+                .iter()
+                .filter(|message| user.is_subscribed(message.modul))
+                .collect::<Vec<_>>();
+
+            for message in subscribed.iter().filter(|v| notify(v)) {
+                let beginn = message.modul_termin.beginn.with_timezone(&Utc);
+                let ende = message.modul_termin.ende.with_timezone(&Utc);
+                for offset in &user.send_before {
+                    let zeit = beginn - Duration::minutes(offset.minutes as i64);
+                    if allowed(zeit) {
+                        let aktion = Aktion::Vorher(user, (*message).clone(), offset.minutes);
+                        push(zeit, ende, aktion);
+                    }
+                }
+            }
+
+            if let Some(time) = user.digest {
+                let tz = user.locale().zeitzone;
+                for zeit in occurrences(after, now, |after| digest::next(time, tz, after)) {
+                    if !user.is_paused(zeit) {
+                        push(zeit, zeit + Duration::days(1), Aktion::Digest(user));
+                    }
+                }
+            }
+
+            if user.send_after_previous {
+                let locale = user.locale();
+                let termine = subscribed
+                    .iter()
+                    .filter(|message| !message.modul_termin.ausfall)
+                    .collect::<Vec<_>>();
+                // Dates ending at the same time only announce the next date once
+                let mut ends = HashSet::new();
+                for previous in &termine {
+                    let ende = previous.modul_termin.ende;
+                    let next = termine
+                        .iter()
+                        .filter(|v| {
+                            v.modul_termin.beginn > ende
+                                && locale.local(&v.modul_termin.beginn).date()
+                                    == locale.local(&ende).date()
+                        })
+                        .min_by_key(|v| v.modul_termin.beginn);
+                    let zeit = ende.with_timezone(&Utc);
+                    if let Some(next) = next.filter(|_| allowed(zeit) && ends.insert(zeit)) {
+                        let ende = next.modul_termin.ende.with_timezone(&Utc);
+                        push(zeit, ende, Aktion::Danach(user, (**next).clone()));
+                    }
+                }
+            }
+        }
+        Self { queue }
+    }
+
+    /// Returns the time of the earliest reminder
+    pub fn next(&self) -> Option<DateTime<Utc>> {
+        self.queue.peek().map(|v| v.0.zeit)
+    }
+
+    /// Removes and returns the earliest reminder if it is due at `now`
+    pub fn pop_due(&mut self, now: DateTime<Utc>) -> Option<Erinnerung<'m>> {
+        match self.next() {
+            Some(zeit) if zeit <= now => self.queue.pop().map(|v| v.0),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the times returned by `next` after `after` up to and including the first one after `now`
+fn occurrences<F>(after: DateTime<Utc>, now: DateTime<Utc>, next: F) -> Vec<DateTime<Utc>>
+where
+    F: Fn(DateTime<Utc>) -> DateTime<Utc>,
+{
+    let mut zeiten = vec![next(after)];
+    while let Some(zeit) = zeiten.last().copied().filter(|v| *v <= now) {
+        zeiten.push(next(zeit));
+    }
+    zeiten
+}
+
+/// Combines the reminders of dates that begin at the same time for the same receiver, so each
+/// receiver gets a single message with a single mention
+pub fn batches(erinnerungen: Vec<Erinnerung<'_>>) -> Vec<Vec<Erinnerung<'_>>> {
+    let mut batches: Vec<Vec<Erinnerung>> = Vec::new();
+    for erinnerung in erinnerungen {
+        let key = erinnerung.batch_key();
+        match batches
+            .iter_mut()
+            .find(|batch| key.is_some() && batch[0].batch_key() == key)
+        {
+            Some(batch) => batch.push(erinnerung),
+            None => batches.push(vec![erinnerung]),
+        }
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::modul::ModulTyp;
+    use chrono::{NaiveTime, TimeZone};
+    use serenity::model::id::UserId;
+
+    fn config() -> Config {
+        toml::from_str(
+            r##"
+            [discord]
+            bot_token = "token"
+            guild_id = 1
+
+            [users]
+            file = "users.toml"
+
+            [[kohorte]]
+            id = "ain1"
+            name = "AIN 1"
+            gruppe = [{ name = "Gruppe 1", channel_id = 11, role_id = 21 }]
+
+            [[kohorte.modul]]
+            id = "digitaltechnik"
+            name = "Digitaltechnik"
+            file = "digitaltechnik.toml"
+            "##,
+        )
+        .unwrap()
+    }
+
+    fn modul(hours: &[(u32, u32)]) -> Modul {
+        let termine = hours
+            .iter()
+            .map(|(beginn, ende)| fixture::termin(5, (*beginn, 0), (*ende, 0)))
+            .collect();
+        fixture::modul("digitaltechnik", None, termine)
+    }
+
+    fn user() -> User {
+        let mut user: User = toml::from_str(
+            r#"
+            id = 1
+            enabled = true
+            send_before = [60, 10]
+            send_after_previous = true
+
+            [[kohorten]]
+            kohorte = "ain1"
+            "#,
+        )
+        .unwrap();
+        user.ruhezeit = Some("09:55-10:05".parse().unwrap());
+        user
+    }
+
+    fn berlin(hour: u32, minute: u32) -> DateTime<Utc> {
+        chrono_tz::Europe::Berlin
+            .ymd(2020, 10, 5)
+            .and_hms(hour, minute, 0)
+            .with_timezone(&Utc)
+    }
+
+    fn fire<'m>(zeitplan: &mut Zeitplan<'m>, now: DateTime<Utc>) -> Vec<(String, DateTime<Utc>)> {
+        std::iter::from_fn(|| zeitplan.pop_due(now))
+            .map(|v| {
+                let name = match v.aktion {
+                    Aktion::Gruppe(..) => "gruppe",
+                    Aktion::Vorschau(..) => "vorschau",
+                    Aktion::Vorher(..) => "vorher",
+                    Aktion::Danach(..) => "danach",
+                    Aktion::Digest(_) => "digest",
+                };
+                (name.to_owned(), v.zeit)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn order() {
+        let config = config();
+        let module = vec![modul(&[(8, 10), (12, 13)])];
+        let users = vec![user()];
+        let mut zeitplan = Zeitplan::new(&config, &module, &users, berlin(6, 0), berlin(6, 0));
+        assert_eq!(zeitplan.next(), Some(berlin(7, 0)));
+        assert!(fire(&mut zeitplan, berlin(6, 59)).is_empty());
+        assert_eq!(
+            fire(&mut zeitplan, berlin(10, 0)),
+            vec![
+                ("vorher".to_owned(), berlin(7, 0)),
+                ("gruppe".to_owned(), berlin(7, 30)),
+                ("vorher".to_owned(), berlin(7, 50)),
+            ]
+        );
+        // The reminder at the end of the first date lies in the quiet hours
+        assert_eq!(zeitplan.next(), Some(berlin(11, 0)));
+    }
+
+    #[test]
+    fn exactly_once() {
+        let config = config();
+        let module = vec![modul(&[(8, 10), (12, 13)])];
+        let mut users = vec![user()];
+        users[0].ruhezeit = None;
+        let mut zeitplan = Zeitplan::new(&config, &module, &users, berlin(6, 0), berlin(6, 0));
+        assert_eq!(fire(&mut zeitplan, berlin(7, 50)).len(), 3);
+
+        // Rebuilding after a change only includes the reminders after the last run
+        let mut zeitplan = Zeitplan::new(&config, &module, &users, berlin(7, 50), berlin(7, 50));
+        assert_eq!(
+            fire(&mut zeitplan, berlin(10, 0)),
+            vec![("danach".to_owned(), berlin(10, 0))]
+        );
+    }
+
+    #[test]
+    fn zustellung() {
+        let config = config();
+        let module = vec![modul(&[(8, 10)])];
+        let users = vec![user()];
+        let mut zeitplan = Zeitplan::new(&config, &module, &users, berlin(6, 0), berlin(6, 0));
+        let erinnerung = zeitplan.pop_due(berlin(7, 0)).unwrap();
+        assert_eq!(
+            erinnerung.zustellung(),
+            Zustellung {
+                ziel: "user/1".to_owned(),
+                termin: "ain1/digitaltechnik//2020-10-05T06:00:00+00:00".to_owned(),
+                offset: "60".to_owned(),
+            }
+        );
+        assert_eq!(erinnerung.ende, berlin(10, 0));
+        assert!(erinnerung.is_current(berlin(7, 59)));
+        assert!(!erinnerung.is_current(berlin(8, 0)));
+
+        // Reminders at the beginning of a date stay current until it ends
+        let aktion = match erinnerung.aktion {
+            Aktion::Vorher(user, message, _) => Aktion::Vorher(user, message, 0),
+            _ => unreachable!(),
+        };
+        let erinnerung = Erinnerung {
+            zeit: berlin(8, 0),
+            aktion,
+            ..erinnerung
+        };
+        assert!(erinnerung.is_current(berlin(9, 59)));
+        assert!(!erinnerung.is_current(berlin(10, 0)));
+    }
+
+    #[test]
+    fn mention_first_only() {
+        let mut config = config();
+        config.reminders.channel.mention_first_only = Some(true);
+        let module = vec![modul(&[(8, 10), (12, 13)])];
+        let mut zeitplan = Zeitplan::new(&config, &module, &[], berlin(6, 0), berlin(6, 0));
+        let mentions = std::iter::from_fn(|| zeitplan.pop_due(berlin(12, 0)))
+            .filter_map(|v| match v.aktion {
+                Aktion::Gruppe(_, _, _, mention) => Some(mention),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(mentions, vec![Mention::Role, Mention::None]);
+    }
+
+    #[test]
+    fn batches() {
+        let config = config();
+        let mut mathematik = modul(&[(8, 10)]);
+        mathematik.typ = ModulTyp::new("ain1", "mathematik", "Mathematik");
+        let module = vec![modul(&[(8, 10)]), mathematik];
+        let users = vec![user()];
+        let mut zeitplan = Zeitplan::new(&config, &module, &users, berlin(6, 0), berlin(6, 0));
+        let due = std::iter::from_fn(|| zeitplan.pop_due(berlin(7, 50))).collect::<Vec<_>>();
+        assert_eq!(due.len(), 6);
+        let batches = super::batches(due);
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![2, 2, 2]
+        );
+    }
+
+    #[test]
+    fn failing_digest() {
+        let config = config();
+        let mut users = vec![user(), user()];
+        users[1].id = UserId(2);
+        for user in &mut users {
+            user.digest = Some(NaiveTime::from_hms(7, 0, 0));
+        }
+        // Runs the reminder loop, where the digest of the first user fails permanently
+        let mut sent = HashSet::new();
+        let mut sent_until = berlin(6, 0);
+        for day in 0..2 {
+            for now in &[berlin(7, 1), berlin(12, 0)] {
+                let now = *now + Duration::days(day);
+                let mut zeitplan = Zeitplan::new(&config, &[], &users, sent_until, now);
+                let mut failed = None;
+                while let Some(erinnerung) = zeitplan.pop_due(now) {
+                    let zustellung = erinnerung.zustellung();
+                    if sent.contains(&zustellung) || !erinnerung.is_current(now) {
+                        continue;
+                    }
+                    if zustellung.ziel == "user/1" {
+                        failed = failed.or(Some(erinnerung.zeit));
+                    } else {
+                        sent.insert(zustellung);
+                    }
+                }
+                sent_until = failed.map_or(now, |v| v - Duration::milliseconds(1));
+            }
+        }
+        let mut days = sent.into_iter().map(|v| v.termin).collect::<Vec<_>>();
+        days.sort();
+        assert_eq!(days, vec!["2020-10-05", "2020-10-06"]);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::modul::Modul;
+use crate::persist;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, fs, path::Path};
+
+/// Name of the snapshot file, which is stored next to the users file
+pub const FILE_NAME: &str = "schedule.json";
+
+/// Version of the on-disk format, has to be increased on incompatible changes of [`Modul`]
+const VERSION: u32 = 3;
+
+/// The last successfully fetched schedule
+#[derive(Deserialize, Serialize)]
+pub struct Snapshot<'a> {
+    pub version: u32,
+    pub fetched_at: DateTime<Utc>,
+    pub module: Cow<'a, [Modul]>,
+}
+
+impl<'a> Snapshot<'a> {
+    pub fn new(module: &'a [Modul]) -> Self {
+        Self {
+            version: VERSION,
+            fetched_at: Utc::now(),
+            module: Cow::Borrowed(module),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize schedule snapshot")
+    }
+
+    pub fn from_json(input: &str) -> Result<Snapshot<'static>> {
+        let snapshot: Snapshot =
+            serde_json::from_str(input).context("Failed to deserialize schedule snapshot")?;
+        if snapshot.version != VERSION {
+            return Err(anyhow!(
+                "Unsupported schedule snapshot version `{}` (expected `{}`)",
+                snapshot.version,
+                VERSION
+            ));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Writes the schedule to the snapshot file
+pub fn save<P: AsRef<Path>>(path: P, module: &[Modul]) -> Result<()> {
+    let string = Snapshot::new(module).to_json()?;
+    persist::write(path, &string, 0).context("Failed to write schedule snapshot")
+}
+
+/// Reads the schedule from the snapshot file
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot<'static>> {
+    let string = fs::read_to_string(path).context("Failed to read schedule snapshot")?;
+    Snapshot::from_json(&string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    fn module() -> Vec<Modul> {
+        let termine = vec![fixture::termin(7, (11, 45), (13, 15))];
+        vec![Modul {
+            raum: Some("F-033".to_owned()),
+            ..fixture::modul("digitaltechnik", Some("Gruppe 1"), termine)
+        }]
+    }
+
+    #[test]
+    fn roundtrip() {
+        let module = module();
+        let string = Snapshot::new(&module).to_json().unwrap();
+        let snapshot = Snapshot::from_json(&string).unwrap();
+        assert_eq!(snapshot.module.as_ref(), module.as_slice());
+        assert_eq!(
+            snapshot.module[0].termine[0].beginn.timezone(),
+            chrono_tz::Europe::Berlin
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let module = module();
+        let mut snapshot = Snapshot::new(&module);
+        snapshot.version = VERSION + 1;
+        let string = snapshot.to_json().unwrap();
+        assert!(Snapshot::from_json(&string).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use crate::config::{Config, Source};
+use crate::modul::{Modul, ModulTyp};
+use anyhow::{Context, Result};
+
+pub mod file;
+pub mod ical;
+pub mod lsf;
+
+/// A source from which the schedule of a single module can be fetched
+pub trait ScheduleSource {
+    fn fetch(&self) -> Result<Vec<Modul>>;
+}
+
+/// Creates the configured schedule source for every module
+pub fn from_config(cfg: &Config) -> Result<Vec<(ModulTyp, Box<dyn ScheduleSource>)>> {
+    let mut sources = Vec::new();
+    for kohorte in &cfg.kohorten {
+        for modul in &kohorte.module {
+            let typ = modul.typ(&kohorte.id);
+            let source: Box<dyn ScheduleSource> = match &modul.source {
+                Source::Lsf(url) => Box::new(lsf::LsfSource::new(url.clone(), kohorte)?),
+                Source::Ical(location) => Box::new(ical::IcalSource::new(
+                    typ.clone(),
+                    ical::Location::parse(location, cfg),
+                )?),
+                Source::File(path) => {
+                    Box::new(file::FileSource::new(typ.clone(), cfg.resolve_path(path)))
+                }
+            };
+            sources.push((typ, source));
+        }
+    }
+    Ok(sources)
+}
+
+/// Fetches the schedule of all modules from their configured sources
+pub fn fetch_module(cfg: &Config) -> Result<Vec<Modul>> {
+    log::debug!("Fetching schedule");
+    let mut module = Vec::new();
+    for (typ, source) in from_config(cfg)? {
+        module.extend(
+            source
+                .fetch()
+                .with_context(|| format!("Failed to fetch schedule of `{}`", typ))?,
+        );
+    }
+    log::debug!("Successfully fetched schedule");
+    Ok(module)
+}
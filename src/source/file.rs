@@ -0,0 +1,179 @@
+use super::ScheduleSource;
+use crate::modul::{Modul, ModulGruppe, ModulTermin, ModulTyp};
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use serde::Deserialize;
+use std::{fs, path::PathBuf};
+
+/// Reads the schedule from a hand-written TOML or JSON file
+///
+/// The file contains a list of entries that mirror the rows of an LSF page:
+///
+/// ```toml
+/// [[modul]]
+/// gruppe = "Gruppe1"
+/// raum = "F-033"
+/// art = "Übung"
+/// dozent = "Erika Musterfrau"
+/// beginn = "11:45"
+/// ende = "13:15"
+/// termine = ["07.10.2020", "14.10.2020"]
+/// ausfall = ["14.10.2020"]
+/// ```
+pub struct FileSource {
+    typ: ModulTyp,
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Schedule {
+    #[serde(default)]
+    modul: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    gruppe: Option<ModulGruppe>,
+    raum: Option<String>,
+    bemerkung: Option<String>,
+    art: Option<String>,
+    dozent: Option<String>,
+    rhythmus: Option<String>,
+    dauer: Option<String>,
+    beginn: String,
+    ende: String,
+    termine: Vec<String>,
+    #[serde(default)]
+    ausfall: Vec<String>,
+}
+
+impl FileSource {
+    pub fn new(typ: ModulTyp, path: PathBuf) -> Self {
+        Self { typ, path }
+    }
+}
+
+impl ScheduleSource for FileSource {
+    fn fetch(&self) -> Result<Vec<Modul>> {
+        log::debug!("Reading schedule file `{}`", self.path.display());
+        let string = fs::read_to_string(&self.path).context("Failed to read schedule file")?;
+        let json = self.path.extension().and_then(|v| v.to_str()) == Some("json");
+        parse(&self.typ, &string, json)
+    }
+}
+
+fn parse(typ: &ModulTyp, string: &str, json: bool) -> Result<Vec<Modul>> {
+    let schedule: Schedule = if json {
+        serde_json::from_str(string)?
+    } else {
+        toml::from_str(string)?
+    };
+    schedule
+        .modul
+        .into_iter()
+        .map(|entry| {
+            let beginn = NaiveTime::parse_from_str(&entry.beginn, "%H:%M")
+                .context("Failed to parse time")?;
+            let ende =
+                NaiveTime::parse_from_str(&entry.ende, "%H:%M").context("Failed to parse time")?;
+            let ausfall = entry
+                .ausfall
+                .iter()
+                .map(|v| NaiveDate::parse_from_str(v, "%d.%m.%Y"))
+                .collect::<Result<Vec<_>, _>>()
+                .context("Failed to parse cancelled date")?;
+            let termine = entry
+                .termine
+                .iter()
+                .map(|termin| {
+                    let date = NaiveDate::parse_from_str(termin, "%d.%m.%Y")
+                        .context("Failed to parse date")?;
+                    Ok(ModulTermin {
+                        beginn: chrono_tz::Europe::Berlin
+                            .from_local_datetime(&NaiveDateTime::new(date, beginn))
+                            .unwrap(),
+                        ende: chrono_tz::Europe::Berlin
+                            .from_local_datetime(&NaiveDateTime::new(date, ende))
+                            .unwrap(),
+                        ausfall: ausfall.contains(&date),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Modul {
+                typ: typ.clone(),
+                gruppe: entry.gruppe,
+                termine,
+                raum: entry.raum,
+                bemerkung: entry.bemerkung,
+                art: entry.art,
+                dozent: entry.dozent,
+                rhythmus: entry.rhythmus,
+                dauer: entry.dauer,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typ() -> ModulTyp {
+        ModulTyp::new("ain1", "digitaltechnik", "Digitaltechnik")
+    }
+
+    fn termin(day: u32, ausfall: bool) -> ModulTermin {
+        let date = chrono_tz::Europe::Berlin.ymd(2020, 10, day);
+        ModulTermin {
+            beginn: date.and_hms(11, 45, 0),
+            ende: date.and_hms(13, 15, 0),
+            ausfall,
+        }
+    }
+
+    fn modul() -> Modul {
+        Modul {
+            typ: typ(),
+            gruppe: Some(ModulGruppe::new("Gruppe 1")),
+            termine: vec![termin(7, false), termin(14, true)],
+            raum: Some("F-033".to_owned()),
+            bemerkung: None,
+            art: Some("Übung".to_owned()),
+            dozent: Some("Erika Musterfrau".to_owned()),
+            rhythmus: None,
+            dauer: None,
+        }
+    }
+
+    #[test]
+    fn toml() {
+        let string = r#"
+            [[modul]]
+            gruppe = "Gruppe 1"
+            raum = "F-033"
+            art = "Übung"
+            dozent = "Erika Musterfrau"
+            beginn = "11:45"
+            ende = "13:15"
+            termine = ["07.10.2020", "14.10.2020"]
+            ausfall = ["14.10.2020"]
+        "#;
+        assert_eq!(parse(&typ(), string, false).unwrap(), vec![modul()]);
+    }
+
+    #[test]
+    fn json() {
+        let string = r#"{"modul": [{
+            "gruppe": "Gruppe 1",
+            "raum": "F-033",
+            "art": "Übung",
+            "dozent": "Erika Musterfrau",
+            "beginn": "11:45",
+            "ende": "13:15",
+            "termine": ["07.10.2020", "14.10.2020"],
+            "ausfall": ["14.10.2020"]
+        }]}"#;
+        assert_eq!(parse(&typ(), string, true).unwrap(), vec![modul()]);
+        assert!(parse(&typ(), string, false).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use super::ScheduleSource;
+use crate::config::Config;
+use crate::modul::{Modul, ModulGruppe, ModulTermin, ModulTyp};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use reqwest::blocking::Client;
+use std::{fs, io::BufReader, path::PathBuf};
+
+const DATETIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+pub enum Location {
+    Url(String),
+    File(PathBuf),
+}
+
+impl Location {
+    pub fn parse(input: &str, cfg: &Config) -> Self {
+        if input.starts_with("http://") || input.starts_with("https://") {
+            Self::Url(input.to_owned())
+        } else {
+            Self::File(cfg.resolve_path(input))
+        }
+    }
+}
+
+/// Reads the schedule from an iCalendar file or URL
+///
+/// Every `VEVENT` becomes a date of the module. The group is taken from the summary of the event
+/// (e.g. `Übung Gruppe 2`), the room from its location, the remark from its description, the
+/// event type from its categories and the lecturer from the common name of its organizer.
+/// Weekly and daily recurrence rules are expanded and events with `STATUS:CANCELLED` are marked as
+/// cancelled. Events with a `RECURRENCE-ID` replace the occurrence of the recurring event with the
+/// same `UID`. Rules with parts other than `FREQ`, `INTERVAL`, `COUNT`, `UNTIL` and `WKST` (e.g.
+/// `BYDAY`) are rejected.
+pub struct IcalSource {
+    typ: ModulTyp,
+    location: Location,
+    client: Client,
+}
+
+impl IcalSource {
+    pub fn new(typ: ModulTyp, location: Location) -> Result<Self> {
+        let client = Client::builder()
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self {
+            typ,
+            location,
+            client,
+        })
+    }
+
+    fn read(&self) -> Result<String> {
+        match &self.location {
+            Location::Url(url) => {
+                log::debug!("Fetching calendar `{}`", url);
+                let response = self
+                    .client
+                    .get(url)
+                    .send()
+                    .context("Failed to send HTTP request")?;
+                response.text().context("Failed to get text of response")
+            }
+            Location::File(path) => {
+                log::debug!("Reading calendar file `{}`", path.display());
+                fs::read_to_string(path).context("Failed to read calendar file")
+            }
+        }
+    }
+}
+
+impl ScheduleSource for IcalSource {
+    fn fetch(&self) -> Result<Vec<Modul>> {
+        parse_calendar(&self.typ, &self.read()?)
+    }
+}
+
+fn parse_calendar(typ: &ModulTyp, text: &str) -> Result<Vec<Modul>> {
+    let mut module = Vec::<Modul>::new();
+    for calendar in ical::IcalParser::new(BufReader::new(text.as_bytes())) {
+        let calendar = calendar.context("Failed to parse calendar")?;
+        // Occurrences of recurring events that are replaced by another event
+        let ersetzt = calendar
+            .events
+            .iter()
+            .filter_map(|event| {
+                let uid = property(event, "UID")?.value.clone()?;
+                let id = property(event, "RECURRENCE-ID")?;
+                Some(parse_datetime(id).map(|v| (uid, v)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for event in calendar.events {
+            let (modul, termine) = parse_event(typ, &event, &ersetzt)?;
+            match module.iter_mut().find(|v| {
+                v.gruppe == modul.gruppe
+                    && v.raum == modul.raum
+                    && v.bemerkung == modul.bemerkung
+                    && v.art == modul.art
+                    && v.dozent == modul.dozent
+            }) {
+                Some(v) => v.termine.extend(termine),
+                None => module.push(Modul { termine, ..modul }),
+            }
+        }
+    }
+    for modul in &mut module {
+        modul.termine.sort_by_key(|termin| termin.beginn);
+    }
+    Ok(module)
+}
+
+fn property<'e>(event: &'e IcalEvent, name: &str) -> Option<&'e Property> {
+    event.properties.iter().find(|v| v.name == name)
+}
+
+fn parse_event(
+    typ: &ModulTyp,
+    event: &IcalEvent,
+    ersetzt: &[(String, DateTime<Tz>)],
+) -> Result<(Modul, Vec<ModulTermin>)> {
+    let property = |name: &str| property(event, name);
+    let value = |name: &str| {
+        property(name)
+            .and_then(|v| v.value.as_deref())
+            .map(|v| v.replace("\\n", "\n").replace("\\,", ",").trim().to_owned())
+            .filter(|v| !v.is_empty())
+    };
+
+    let beginn = parse_datetime(property("DTSTART").context("Event without `DTSTART`")?)?;
+    let ende = parse_datetime(property("DTEND").context("Event without `DTEND`")?)?;
+    let mut exdates = event
+        .properties
+        .iter()
+        .filter(|v| v.name == "EXDATE")
+        .map(parse_datetime)
+        .collect::<Result<Vec<_>>>()?;
+    if property("RECURRENCE-ID").is_none() {
+        if let Some(uid) = value("UID") {
+            exdates.extend(ersetzt.iter().filter(|(v, _)| *v == uid).map(|(_, v)| *v));
+        }
+    }
+    let ausfall = value("STATUS").as_deref() == Some("CANCELLED");
+    let termine = match value("RRULE") {
+        Some(rrule) => expand_rrule(&rrule, beginn, ende - beginn)?,
+        None => vec![ModulTermin {
+            beginn,
+            ende,
+            ausfall: false,
+        }],
+    }
+    .into_iter()
+    .filter(|termin| !exdates.contains(&termin.beginn))
+    .map(|termin| ModulTermin { ausfall, ..termin })
+    .collect();
+
+    let gruppe = value("SUMMARY").and_then(|summary| {
+        let index = summary.find("Gruppe ")?;
+        let name = summary[index..]
+            .split_whitespace()
+            .take(2)
+            .collect::<Vec<_>>();
+        Some(ModulGruppe::new(&name.join(" ")))
+    });
+
+    let modul = Modul {
+        typ: typ.clone(),
+        gruppe,
+        termine: Vec::new(),
+        raum: value("LOCATION"),
+        bemerkung: value("DESCRIPTION"),
+        art: value("CATEGORIES"),
+        dozent: property("ORGANIZER").and_then(|v| {
+            v.params
+                .iter()
+                .flatten()
+                .find(|(name, _)| name == "CN")
+                .and_then(|(_, values)| values.first())
+                .map(|v| v.trim_matches('"').to_owned())
+        }),
+        rhythmus: None,
+        dauer: None,
+    };
+    Ok((modul, termine))
+}
+
+fn parse_datetime(property: &Property) -> Result<DateTime<Tz>> {
+    let value = property.value.as_deref().context("Missing value of date")?;
+    if let Some(value) = value.strip_suffix('Z') {
+        let datetime = NaiveDateTime::parse_from_str(value, DATETIME_FORMAT)
+            .context("Failed to parse date")?;
+        return Ok(Utc
+            .from_utc_datetime(&datetime)
+            .with_timezone(&chrono_tz::Europe::Berlin));
+    }
+    let tz = property
+        .params
+        .iter()
+        .flatten()
+        .find(|(name, _)| name == "TZID")
+        .and_then(|(_, values)| values.first())
+        .map(|v| v.parse::<Tz>().map_err(|e| anyhow!(e)))
+        .transpose()
+        .context("Failed to parse time zone")?
+        .unwrap_or(chrono_tz::Europe::Berlin);
+    let datetime =
+        NaiveDateTime::parse_from_str(value, DATETIME_FORMAT).context("Failed to parse date")?;
+    Ok(tz
+        .from_local_datetime(&datetime)
+        .earliest()
+        .context("Invalid local date")?
+        .with_timezone(&chrono_tz::Europe::Berlin))
+}
+
+fn expand_rrule(rrule: &str, beginn: DateTime<Tz>, dauer: Duration) -> Result<Vec<ModulTermin>> {
+    let mut step = None;
+    let mut interval = 1i32;
+    let mut count = None;
+    let mut until = None;
+    for part in rrule.split(';') {
+        let mut split = part.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some("FREQ"), Some("DAILY")) => step = Some(Duration::days(1)),
+            (Some("FREQ"), Some("WEEKLY")) => step = Some(Duration::weeks(1)),
+            (Some("FREQ"), Some(v)) => return Err(anyhow!("Unsupported frequency `{}`", v)),
+            (Some("INTERVAL"), Some(v)) => {
+                interval = v
+                    .parse()
+                    .ok()
+                    .filter(|v| *v >= 1)
+                    .with_context(|| format!("Invalid interval `{}`", v))?
+            }
+            (Some("COUNT"), Some(v)) => count = Some(v.parse::<usize>().context("Invalid count")?),
+            (Some("UNTIL"), Some(v)) => until = Some(parse_until(v)?),
+            (Some("WKST"), Some(_)) | (Some(""), None) | (None, _) => {}
+            (Some(name), _) => return Err(anyhow!("Unsupported recurrence rule part `{}`", name)),
+        }
+    }
+    let step = step.context("Recurrence rule without frequency")? * interval;
+    if count.is_none() && until.is_none() {
+        return Err(anyhow!("Unbounded recurrence rule `{}`", rrule));
+    }
+
+    let mut termine = Vec::new();
+    let mut current = beginn.naive_local();
+    loop {
+        if count.is_some_and(|count| termine.len() >= count) {
+            break;
+        }
+        let beginn = chrono_tz::Europe::Berlin
+            .from_local_datetime(&current)
+            .earliest()
+            .context("Invalid local date")?;
+        if until.is_some_and(|until| beginn.with_timezone(&Utc) > until) {
+            break;
+        }
+        termine.push(ModulTermin {
+            beginn,
+            ende: beginn + dauer,
+            ausfall: false,
+        });
+        current += step;
+    }
+    Ok(termine)
+}
+
+/// Parses the end of a recurrence, which is either in UTC, a local time or a date
+fn parse_until(value: &str) -> Result<DateTime<Utc>> {
+    if let Some(value) = value.strip_suffix('Z') {
+        let datetime = NaiveDateTime::parse_from_str(value, DATETIME_FORMAT)
+            .context("Invalid end of recurrence")?;
+        return Ok(Utc.from_utc_datetime(&datetime));
+    }
+    let value = if value.len() == 8 {
+        format!("{}T235959", value)
+    } else {
+        value.to_owned()
+    };
+    let datetime = NaiveDateTime::parse_from_str(&value, DATETIME_FORMAT)
+        .context("Invalid end of recurrence")?;
+    Ok(chrono_tz::Europe::Berlin
+        .from_local_datetime(&datetime)
+        .earliest()
+        .context("Invalid local date")?
+        .with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typ() -> ModulTyp {
+        ModulTyp::new("ain1", "digitaltechnik", "Digitaltechnik")
+    }
+
+    fn calendar(events: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n",
+            events.replace('\n', "\r\n")
+        )
+    }
+
+    fn termin(day: u32, beginn: (u32, u32), ende: (u32, u32)) -> ModulTermin {
+        let date = chrono_tz::Europe::Berlin.ymd(2020, 10, day);
+        ModulTermin {
+            beginn: date.and_hms(beginn.0, beginn.1, 0),
+            ende: date.and_hms(ende.0, ende.1, 0),
+            ausfall: false,
+        }
+    }
+
+    #[test]
+    fn recurring_event() {
+        let text = calendar(
+            "BEGIN:VEVENT
+UID:vorlesung
+SUMMARY:Digitaltechnik
+LOCATION:O-001
+CATEGORIES:Vorlesung
+ORGANIZER;CN=\"Prof. Dr. Mustermann\":mailto:mustermann@example.org
+DTSTART;TZID=Europe/Berlin:20201005T080000
+DTEND;TZID=Europe/Berlin:20201005T093000
+RRULE:FREQ=WEEKLY;UNTIL=20201026T070000Z
+EXDATE;TZID=Europe/Berlin:20201012T080000
+END:VEVENT
+BEGIN:VEVENT
+UID:vorlesung
+RECURRENCE-ID;TZID=Europe/Berlin:20201019T080000
+SUMMARY:Digitaltechnik
+LOCATION:O-001
+CATEGORIES:Vorlesung
+ORGANIZER;CN=\"Prof. Dr. Mustermann\":mailto:mustermann@example.org
+DTSTART;TZID=Europe/Berlin:20201019T100000
+DTEND;TZID=Europe/Berlin:20201019T113000
+STATUS:CANCELLED
+END:VEVENT
+",
+        );
+        let module = parse_calendar(&typ(), &text).unwrap();
+        assert_eq!(
+            module,
+            vec![Modul {
+                typ: typ(),
+                gruppe: None,
+                termine: vec![
+                    termin(5, (8, 0), (9, 30)),
+                    ModulTermin {
+                        ausfall: true,
+                        ..termin(19, (10, 0), (11, 30))
+                    },
+                    termin(26, (8, 0), (9, 30)),
+                ],
+                raum: Some("O-001".to_owned()),
+                bemerkung: None,
+                art: Some("Vorlesung".to_owned()),
+                dozent: Some("Prof. Dr. Mustermann".to_owned()),
+                rhythmus: None,
+                dauer: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn group_in_utc() {
+        let text = calendar(
+            "BEGIN:VEVENT
+UID:uebung
+SUMMARY:Übung Gruppe 2
+LOCATION:F-033
+DESCRIPTION:Labor
+DTSTART:20201007T094500Z
+DTEND:20201007T111500Z
+RRULE:FREQ=DAILY;INTERVAL=7;COUNT=2
+END:VEVENT
+",
+        );
+        let module = parse_calendar(&typ(), &text).unwrap();
+        assert_eq!(module.len(), 1);
+        assert_eq!(module[0].gruppe, Some(ModulGruppe::new("Gruppe 2")));
+        assert_eq!(module[0].bemerkung.as_deref(), Some("Labor"));
+        assert_eq!(
+            module[0].termine,
+            vec![
+                termin(7, (11, 45), (13, 15)),
+                termin(14, (11, 45), (13, 15))
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_rules() {
+        let beginn = termin(5, (8, 0), (9, 30)).beginn;
+        for rrule in &[
+            "FREQ=MONTHLY;COUNT=2",
+            "FREQ=WEEKLY;BYDAY=MO,WE;COUNT=2",
+            "FREQ=WEEKLY;INTERVAL=0;COUNT=2",
+            "FREQ=WEEKLY",
+        ] {
+            assert!(
+                expand_rrule(rrule, beginn, Duration::minutes(90)).is_err(),
+                "{}",
+                rrule
+            );
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use super::ScheduleSource;
+use crate::config::Kohorte;
+use crate::modul::{Modul, ModulTyp};
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use scraper::Html;
+use std::{thread, time::Duration};
+
+pub mod parser;
+
+/// Scrapes the schedule from an LSF Einzelansicht page
+pub struct LsfSource {
+    client: Client,
+    url: String,
+    titles: Vec<(String, ModulTyp)>,
+}
+
+impl LsfSource {
+    pub fn new(url: String, kohorte: &Kohorte) -> Result<Self> {
+        let client = Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .context("Failed to create HTTP client")?;
+        let titles = kohorte
+            .module
+            .iter()
+            .map(|modul| (modul.lsf_title().to_owned(), modul.typ(&kohorte.id)))
+            .collect();
+        Ok(Self {
+            client,
+            url,
+            titles,
+        })
+    }
+
+    fn fetch_document(&self, url: &str) -> Result<Html> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .context("Failed to send HTTP request")?;
+        let text = response.text().context("Failed to get text of response")?;
+        Ok(Html::parse_document(&text))
+    }
+}
+
+impl ScheduleSource for LsfSource {
+    fn fetch(&self) -> Result<Vec<Modul>> {
+        log::debug!("Fetching data from website `{}`", self.url);
+        let document = self.fetch_document(&self.url)?;
+        let expanded = parser::expand_links(&document)?
+            .iter()
+            .map(|link| self.fetch_document(link))
+            .collect::<Result<Vec<_>>>()?;
+        let module = parser::parse(&document, &expanded, &self.titles)?;
+        thread::sleep(Duration::from_secs(2));
+        Ok(module)
+    }
+}
@@ -0,0 +1,401 @@
+use crate::modul::{Modul, ModulGruppe, ModulTermin, ModulTyp};
+use anyhow::{anyhow, Context, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use scraper::{ElementRef, Html, Selector};
+
+const TABLE_SELECTOR: &str = "table[summary='Übersicht über alle Veranstaltungstermine']";
+const ROW_SELECTOR: &str = "tbody > tr:nth-child(n+2)";
+
+/// Returns the links of the expand buttons of all rows on an Einzelansicht page
+///
+/// The documents behind these links have to be passed to [`parse`] in the same order.
+pub fn expand_links(document: &Html) -> Result<Vec<String>> {
+    let table_selector = Selector::parse(TABLE_SELECTOR).unwrap();
+    let row_selector = Selector::parse(ROW_SELECTOR).unwrap();
+    let expand_link_selector = Selector::parse("td:first-child > a:first-child").unwrap();
+
+    let mut links = Vec::new();
+    for table in document.select(&table_selector) {
+        for row in table.select(&row_selector) {
+            let expand_link = row
+                .select(&expand_link_selector)
+                .next()
+                .context("Failed to get expand button")?
+                .value()
+                .attr("href")
+                .context("Failed to get `href` attribute of expand button")?;
+            links.push(expand_link.to_owned());
+        }
+    }
+    Ok(links)
+}
+
+/// Parses an Einzelansicht page into a list of modules
+///
+/// `expanded` contains the documents behind the links returned by [`expand_links`], one for each
+/// row of the page. `titles` maps the LSF titles of the configured modules to their type.
+pub fn parse(
+    document: &Html,
+    expanded: &[Html],
+    titles: &[(String, ModulTyp)],
+) -> Result<Vec<Modul>> {
+    let table_selector = Selector::parse(TABLE_SELECTOR).unwrap();
+    let row_selector = Selector::parse(ROW_SELECTOR).unwrap();
+
+    let modul_typ = parse_typ(document, titles)?;
+    let art = parse_art(document);
+
+    let mut module = Vec::<Modul>::new();
+    let mut expanded = expanded.iter();
+    for (i, table) in document.select(&table_selector).enumerate() {
+        let gruppe = parse_gruppe(table)?;
+        for (j, row) in table.select(&row_selector).enumerate() {
+            let expanded_document = expanded
+                .next()
+                .context("Missing expanded document for row")?;
+            let expanded_table = expanded_document
+                .select(&table_selector)
+                .nth(i)
+                .context("Failed to get table")?;
+
+            let raum_selector = Selector::parse("td:nth-child(6) > a").unwrap();
+            let raum = row
+                .select(&raum_selector)
+                .next()
+                .map(|v| v.inner_html().trim().to_owned());
+
+            let bemerkung_selector = Selector::parse("td:nth-child(10)").unwrap();
+            let bemerkung = row
+                .select(&bemerkung_selector)
+                .next()
+                .map(|v| v.inner_html().trim().to_owned());
+
+            let rhythmus = cell_text(row, 4);
+            let dauer = cell_text(row, 5);
+            let dozent = cell_text(row, 8);
+
+            let (zeit_beginn, zeit_ende) = parse_zeit(row)?;
+            let ausfall = parse_ausfall(row)?;
+
+            let termine_row = expanded_table
+                .select(&row_selector)
+                .nth(j + 1)
+                .context("Failed to get dates")?;
+            let termine = parse_termine(termine_row, zeit_beginn, zeit_ende, &ausfall)?;
+            if termine.is_empty() {
+                log::warn!("Found entry without any dates");
+            }
+
+            merge(
+                &mut module,
+                Modul {
+                    typ: modul_typ.clone(),
+                    gruppe: gruppe.clone(),
+                    termine,
+                    raum,
+                    bemerkung,
+                    art: art.clone(),
+                    dozent,
+                    rhythmus,
+                    dauer,
+                },
+            );
+        }
+    }
+    Ok(module)
+}
+
+fn parse_typ(document: &Html, titles: &[(String, ModulTyp)]) -> Result<ModulTyp> {
+    let name_selector = Selector::parse("div > form > h1").unwrap();
+    let name = document
+        .select(&name_selector)
+        .next()
+        .context("Failed to get name")?
+        .inner_html();
+    let name = name.trim();
+    let name = name.trim_end_matches(" - Einzelansicht");
+    match titles.iter().find(|(title, _)| title == name) {
+        Some((_, typ)) => Ok(typ.clone()),
+        None => Err(anyhow!(
+            "Unknown module `{}` (configured modules: {})",
+            name,
+            titles
+                .iter()
+                .map(|(title, _)| format!("`{}`", title))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Returns the `Veranstaltungsart` from the table with the basic data of the module
+fn parse_art(document: &Html) -> Option<String> {
+    let art_selector =
+        Selector::parse("table[summary='Grunddaten zur Veranstaltung'] td[headers='basic_1']")
+            .unwrap();
+    document
+        .select(&art_selector)
+        .next()
+        .map(|v| v.text().collect::<String>().trim().to_owned())
+        .filter(|v| !v.is_empty())
+}
+
+/// Returns the trimmed text of the `n`-th column of a row, or `None` if it is empty
+fn cell_text(row: ElementRef, n: usize) -> Option<String> {
+    let selector = Selector::parse(&format!("td:nth-child({})", n)).unwrap();
+    let text = row
+        .select(&selector)
+        .next()?
+        .text()
+        .collect::<String>()
+        .replace('\u{a0}', " ");
+    Some(text.trim().to_owned()).filter(|v| !v.is_empty())
+}
+
+fn parse_gruppe(table: ElementRef) -> Result<Option<ModulGruppe>> {
+    let gruppe_selector = Selector::parse("caption.t_capt").unwrap();
+    let gruppe = table
+        .select(&gruppe_selector)
+        .next()
+        .context("Failed to get group")?;
+    let gruppe = gruppe
+        .text()
+        .next()
+        .context("Failed to get text of group")?
+        .trim();
+    let gruppe = gruppe.trim_start_matches("Termine Gruppe: ");
+    match gruppe {
+        "[unbenannt]" => Ok(None),
+        v => Ok(Some(ModulGruppe::new(v))),
+    }
+}
+
+fn parse_zeit(row: ElementRef) -> Result<(NaiveTime, NaiveTime)> {
+    let zeit_selector = Selector::parse("td:nth-child(3)").unwrap();
+    let zeit = row
+        .select(&zeit_selector)
+        .next()
+        .context("Failed to get time")?
+        .inner_html();
+    let zeit = zeit.replace("&nbsp;", " ");
+    let mut split = zeit.trim().split(" bis ");
+    let zeit_beginn =
+        NaiveTime::parse_from_str(split.next().context("Failed to parse time")?, "%H:%M")
+            .context("Failed to parse time")?;
+    let zeit_ende =
+        NaiveTime::parse_from_str(split.next().context("Failed to parse time")?, "%H:%M")
+            .context("Failed to parse time")?;
+    Ok((zeit_beginn, zeit_ende))
+}
+
+/// Parses the dates of the `fällt aus am` column
+fn parse_ausfall(row: ElementRef) -> Result<Vec<NaiveDate>> {
+    let ausfall_selector = Selector::parse("td:nth-child(11)").unwrap();
+    let ausfall = match row.select(&ausfall_selector).next() {
+        Some(v) => v,
+        None => return Ok(Vec::new()),
+    };
+    ausfall
+        .text()
+        .flat_map(|v| v.split_whitespace())
+        .filter(|v| v.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|v| NaiveDate::parse_from_str(v, "%d.%m.%Y").context("Failed to parse cancelled date"))
+        .collect()
+}
+
+fn parse_termine(
+    termine_row: ElementRef,
+    zeit_beginn: NaiveTime,
+    zeit_ende: NaiveTime,
+    ausfall: &[NaiveDate],
+) -> Result<Vec<ModulTermin>> {
+    let termine_selector = Selector::parse("td > div > ul > li").unwrap();
+    let mut termine = Vec::new();
+    for termin in termine_row.select(&termine_selector) {
+        let date = NaiveDate::parse_from_str(
+            termin
+                .text()
+                .next()
+                .context("Failed to get text of date")?
+                .trim(),
+            "%d.%m.%Y",
+        )
+        .context("Failed to parse date")?;
+        termine.push(ModulTermin {
+            beginn: chrono_tz::Europe::Berlin
+                .from_local_datetime(&NaiveDateTime::new(date, zeit_beginn))
+                .unwrap(),
+            ende: chrono_tz::Europe::Berlin
+                .from_local_datetime(&NaiveDateTime::new(date, zeit_ende))
+                .unwrap(),
+            ausfall: ausfall.contains(&date),
+        });
+    }
+    Ok(termine)
+}
+
+/// Adds a module to the list, merging the room and lecturer into an existing module with the same
+/// dates
+fn merge(module: &mut Vec<Modul>, modul: Modul) {
+    match (
+        &modul.raum,
+        module
+            .iter_mut()
+            .find(|v| v.termine == modul.termine && v.typ == modul.typ && v.gruppe == modul.gruppe),
+    ) {
+        (
+            Some(raum),
+            Some(Modul {
+                raum: Some(modul_raum),
+                dozent: modul_dozent,
+                ..
+            }),
+        ) => {
+            modul_raum.push_str(" & ");
+            modul_raum.push_str(raum);
+            match (modul_dozent, modul.dozent) {
+                (Some(modul_dozent), Some(dozent)) if !modul_dozent.contains(&dozent) => {
+                    modul_dozent.push_str(" & ");
+                    modul_dozent.push_str(&dozent);
+                }
+                (modul_dozent @ None, dozent) => *modul_dozent = dozent,
+                _ => {}
+            }
+        }
+        _ => module.push(modul),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typ() -> ModulTyp {
+        ModulTyp::new("ain1", "digitaltechnik", "Digitaltechnik")
+    }
+
+    fn titles() -> Vec<(String, ModulTyp)> {
+        vec![("AIN1 Digitaltechnik".to_owned(), typ())]
+    }
+
+    fn digitaltechnik() -> (Html, Vec<Html>) {
+        let document = Html::parse_document(include_str!("fixtures/digitaltechnik.html"));
+        let expanded = vec![
+            Html::parse_document(include_str!("fixtures/digitaltechnik_expanded_0.html")),
+            Html::parse_document(include_str!("fixtures/digitaltechnik_expanded_1.html")),
+            Html::parse_document(include_str!("fixtures/digitaltechnik_expanded_2.html")),
+            Html::parse_document(include_str!("fixtures/digitaltechnik_expanded_3.html")),
+        ];
+        (document, expanded)
+    }
+
+    fn termin(date: (i32, u32, u32), beginn: (u32, u32), ende: (u32, u32)) -> ModulTermin {
+        let date = chrono_tz::Europe::Berlin.ymd(date.0, date.1, date.2);
+        ModulTermin {
+            beginn: date.and_hms(beginn.0, beginn.1, 0),
+            ende: date.and_hms(ende.0, ende.1, 0),
+            ausfall: false,
+        }
+    }
+
+    #[test]
+    fn expand_links_in_row_order() {
+        let (document, _) = digitaltechnik();
+        let links = expand_links(&document).unwrap();
+        assert_eq!(links.len(), 4);
+        for (link, id) in links.iter().zip(&["401", "402", "403", "404"]) {
+            assert!(
+                link.ends_with(&format!("&expand={}#{}", id, id)),
+                "{}",
+                link
+            );
+        }
+    }
+
+    #[test]
+    fn unnamed_group_with_multiple_rooms() {
+        let (document, expanded) = digitaltechnik();
+        let module = parse(&document, &expanded, &titles()).unwrap();
+        assert_eq!(module.len(), 3);
+        assert_eq!(
+            module[0],
+            Modul {
+                typ: typ(),
+                gruppe: None,
+                termine: vec![
+                    termin((2020, 10, 5), (8, 0), (9, 30)),
+                    ModulTermin {
+                        ausfall: true,
+                        ..termin((2020, 10, 12), (8, 0), (9, 30))
+                    },
+                    termin((2020, 10, 19), (8, 0), (9, 30)),
+                ],
+                raum: Some("O-001 & O-002".to_owned()),
+                bemerkung: Some(String::new()),
+                art: Some("Vorlesung/Übung".to_owned()),
+                dozent: Some("Prof. Dr. Mustermann".to_owned()),
+                rhythmus: Some("wöch".to_owned()),
+                dauer: Some("05.10.2020 bis 19.10.2020".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn group() {
+        let (document, expanded) = digitaltechnik();
+        let module = parse(&document, &expanded, &titles()).unwrap();
+        assert_eq!(
+            module[1],
+            Modul {
+                typ: typ(),
+                gruppe: Some(ModulGruppe::new("Gruppe 1")),
+                termine: vec![termin((2020, 10, 7), (11, 45), (13, 15))],
+                raum: Some("F-033".to_owned()),
+                bemerkung: Some("Labor".to_owned()),
+                art: Some("Vorlesung/Übung".to_owned()),
+                dozent: Some("Erika Musterfrau".to_owned()),
+                rhythmus: Some("Einzel".to_owned()),
+                dauer: Some("am 07.10.2020".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn row_without_dates() {
+        let (document, expanded) = digitaltechnik();
+        let module = parse(&document, &expanded, &titles()).unwrap();
+        assert_eq!(
+            module[2],
+            Modul {
+                typ: typ(),
+                gruppe: Some(ModulGruppe::new("Gruppe 1")),
+                termine: Vec::new(),
+                raum: None,
+                bemerkung: Some(String::new()),
+                art: Some("Vorlesung/Übung".to_owned()),
+                dozent: Some("Erika Musterfrau".to_owned()),
+                rhythmus: Some("wöch".to_owned()),
+                dauer: None,
+            }
+        );
+    }
+
+    #[test]
+    fn missing_expanded_document() {
+        let (document, mut expanded) = digitaltechnik();
+        expanded.pop();
+        assert!(parse(&document, &expanded, &titles()).is_err());
+    }
+
+    #[test]
+    fn unknown_name() {
+        let document = Html::parse_document(
+            "<html><body><div><form><h1>AIN1 Unbekannt - Einzelansicht</h1></form></div></body></html>",
+        );
+        let error = parse(&document, &[], &titles()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown module `AIN1 Unbekannt` (configured modules: `AIN1 Digitaltechnik`)"
+        );
+    }
+}
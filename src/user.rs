@@ -1,172 +1,538 @@
-use crate::modul::ModulGruppe;
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use serenity::{model::id::UserId, CacheAndHttp};
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
-use std::{fs::OpenOptions, sync::Arc};
-
-#[derive(Deserialize, Serialize)]
-struct Config {
-    #[serde(default)]
-    user: Vec<User>,
-}
+use crate::config::Backend;
+use crate::locale::{Locale, Sprache};
+use crate::modul::{Modul, ModulGruppe};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize};
+use serenity::model::id::UserId;
+use std::convert::TryFrom;
+use std::{fmt, str::FromStr, sync::Arc};
 
-impl Config {
-    fn new<P: AsRef<Path>>(file_path: P) -> Result<Self> {
-        let mut file = OpenOptions::new().read(true).open(file_path)?;
-        let mut string = String::new();
-        file.read_to_string(&mut string)?;
-        Ok(toml::from_str(&string)?)
-    }
-}
+pub mod file;
+pub mod sqlite;
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(transparent)]
 pub struct Duration {
     pub minutes: u64,
 }
 
+/// Reads either a list of offsets or a single offset, which was the only option before
+fn deserialize_send_before<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum SendBefore {
+        Single(Duration),
+        List(Vec<Duration>),
+    }
+    Ok(match Option::<SendBefore>::deserialize(deserializer)? {
+        Some(SendBefore::Single(v)) => vec![v],
+        Some(SendBefore::List(v)) => v,
+        None => Vec::new(),
+    })
+}
+
+/// (De)serializes a time as `HH:MM`
+mod uhrzeit {
+    use chrono::NaiveTime;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    const FORMAT: &str = "%H:%M";
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<NaiveTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .map(|v| v.format(FORMAT).to_string())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NaiveTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|v| NaiveTime::parse_from_str(&v, FORMAT).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct User {
     pub id: UserId,
-    pub gruppe: Option<ModulGruppe>,
+    /// Group of users files that were written before cohorts existed
+    #[serde(default, skip_serializing)]
+    pub(crate) gruppe: Option<ModulGruppe>,
     pub enabled: bool,
-    pub send_before: Option<Duration>,
+    /// Offsets before the beginning of a date at which a reminder is sent
+    #[serde(default, deserialize_with = "deserialize_send_before")]
+    pub send_before: Vec<Duration>,
     pub send_after_previous: bool,
+    /// Local time of the daily digest
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "uhrzeit")]
+    pub digest: Option<NaiveTime>,
+    /// Local time span in which no reminders are sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ruhezeit: Option<Ruhezeit>,
+    /// Last day on which no direct messages are sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_bis: Option<NaiveDate>,
+    /// Language of the messages sent to the user
+    #[serde(default)]
+    pub sprache: Sprache,
+    /// Time zone of the messages and of the times set by the user, defaults to `Europe/Berlin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zeitzone: Option<Tz>,
+    #[serde(default)]
+    pub abo: Abo,
+    #[serde(default)]
+    pub kohorten: Vec<Mitgliedschaft>,
 }
 
-pub struct Users {
-    file_path: PathBuf,
-    users_config: Config,
-    app_config: Arc<crate::Config>,
-    cache_and_http: Arc<CacheAndHttp>,
+impl User {
+    /// Returns the language and time zone in which messages are sent to the user
+    pub fn locale(&self) -> Locale {
+        Locale {
+            sprache: self.sprache,
+            zeitzone: self.zeitzone.unwrap_or(chrono_tz::Europe::Berlin),
+        }
+    }
+
+    /// Returns whether the dates of a module concern the user
+    pub fn is_affected(&self, modul: &Modul) -> bool {
+        affects(&self.kohorten, modul)
+    }
+
+    /// Applies the groups detected from the Discord roles to the cohorts of the user and returns
+    /// whether a group changed
+    ///
+    /// Groups set with `dm set group` are kept, and cohorts are only changed with
+    /// `dm set cohorts`.
+    pub fn sync_kohorten(&mut self, detected: &[Mitgliedschaft]) -> bool {
+        let mut changed = false;
+        for mitgliedschaft in self.kohorten.iter_mut().filter(|v| !v.manuell) {
+            let gruppe = detected
+                .iter()
+                .find(|v| v.kohorte == mitgliedschaft.kohorte)
+                .and_then(|v| v.gruppe.clone());
+            if mitgliedschaft.gruppe != gruppe {
+                mitgliedschaft.gruppe = gruppe;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Returns whether the user receives direct messages for the dates of a module
+    pub fn is_subscribed(&self, modul: &Modul) -> bool {
+        self.is_affected(modul) && self.abo.includes(modul)
+    }
+
+    /// Returns whether direct messages are paused at `now`, they resume automatically on the day
+    /// after `pause_bis`
+    pub fn is_paused(&self, now: DateTime<Utc>) -> bool {
+        let today = self.locale().local(&now).date().naive_local();
+        self.pause_bis.is_some_and(|v| today <= v)
+    }
+
+    /// Returns whether `now` lies in the quiet hours of the user
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        let time = self.locale().local(&now).time();
+        self.ruhezeit.as_ref().is_some_and(|v| v.contains(time))
+    }
+}
+
+/// Local time span (e.g. `22:00-07:00`), which may span midnight
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Ruhezeit {
+    pub von: NaiveTime,
+    pub bis: NaiveTime,
+}
+
+impl Ruhezeit {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.von <= self.bis {
+            self.von <= time && time < self.bis
+        } else {
+            time >= self.von || time < self.bis
+        }
+    }
 }
 
-impl Users {
-    pub fn new(
-        file_path: PathBuf,
-        app_config: Arc<crate::Config>,
-        cache_and_http: Arc<CacheAndHttp>,
-    ) -> Result<Self> {
+impl FromStr for Ruhezeit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut split = s.splitn(2, '-');
+        let mut time = || -> Result<NaiveTime> {
+            let value = split.next().unwrap_or_default().trim();
+            NaiveTime::parse_from_str(value, "%H:%M")
+                .map_err(|_| anyhow!("Invalid time `{}` (expected `HH:MM`)", value))
+        };
         Ok(Self {
-            users_config: Config::new(&file_path)?,
-            file_path,
-            app_config,
-            cache_and_http,
+            von: time()?,
+            bis: time()?,
         })
     }
+}
+
+impl TryFrom<String> for Ruhezeit {
+    type Error = anyhow::Error;
 
-    pub fn refresh(&mut self) -> Result<()> {
-        self.users_config = Config::new(&self.file_path)?;
-        Ok(())
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
     }
+}
 
-    pub fn get_all(&self) -> &[User] {
-        &self.users_config.user
+impl From<Ruhezeit> for String {
+    fn from(value: Ruhezeit) -> Self {
+        value.to_string()
     }
+}
 
-    pub fn get(&self, user_id: UserId) -> Option<&User> {
-        self.users_config.user.iter().find(|v| v.id == user_id)
+impl fmt::Display for Ruhezeit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.von.format("%H:%M"),
+            self.bis.format("%H:%M")
+        )
     }
+}
 
-    fn write(&mut self) -> Result<()> {
-        let mut file = OpenOptions::new().write(true).open(&self.file_path)?;
-        let string = toml::to_string_pretty(&self.users_config)?;
-        file.write_all(string.as_bytes())?;
-        Ok(())
+/// Modules and kinds of dates for which a user receives direct messages
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Abo {
+    /// Keys of the muted modules (e.g. `ain1/digitaltechnik`)
+    #[serde(default)]
+    pub stumm: Vec<String>,
+    #[serde(default = "Abo::default_enabled")]
+    pub vorlesungen: bool,
+    #[serde(default = "Abo::default_enabled")]
+    pub uebungen: bool,
+}
+
+impl Abo {
+    fn default_enabled() -> bool {
+        true
     }
 
-    fn get_mut_or_add(&mut self, user_id: UserId) -> Result<&mut User> {
-        if let Some(i) = self
-            .users_config
-            .user
-            .iter()
-            .position(|user| user.id == user_id)
-        {
-            return Ok(&mut self.users_config.user[i]);
-        }
-        let user = user_id.to_user(&self.cache_and_http)?;
-        let user_has_role = |role_id: u64| match user.has_role(
-            &self.cache_and_http,
-            self.app_config.discord.guild_id,
-            role_id,
-        ) {
-            Ok(v) => v,
-            Err(_) => false,
-        };
-        let gruppe = if user_has_role(self.app_config.discord.gruppe_1.role_id) {
-            Some(ModulGruppe::Gruppe1)
-        } else if user_has_role(self.app_config.discord.gruppe_2.role_id) {
-            Some(ModulGruppe::Gruppe2)
-        } else if user_has_role(self.app_config.discord.gruppe_3.role_id) {
-            Some(ModulGruppe::Gruppe3)
-        } else if user_has_role(self.app_config.discord.gruppe_4.role_id) {
-            Some(ModulGruppe::Gruppe4)
+    pub fn includes(&self, modul: &Modul) -> bool {
+        let art = if modul.is_uebung() {
+            self.uebungen
         } else {
-            None
+            self.vorlesungen
         };
-        let users = &mut self.users_config.user;
-        users.push(User {
-            id: user_id,
-            gruppe,
-            enabled: false,
-            send_before: Some(Duration { minutes: 30 }),
-            send_after_previous: false,
-        });
-        Ok(self
-            .users_config
-            .user
-            .iter_mut()
-            .find(|user| user.id == user_id)
-            .unwrap())
+        art && !self.stumm.contains(&modul.typ.key())
     }
+}
 
-    pub fn enable(&mut self, user_id: UserId) -> Result<()> {
-        let user = self.get_mut_or_add(user_id)?;
-        user.enabled = true;
-        self.write()
+impl Default for Abo {
+    fn default() -> Self {
+        Self {
+            stumm: Vec::new(),
+            vorlesungen: true,
+            uebungen: true,
+        }
     }
+}
+
+/// Membership of a user in a cohort
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Mitgliedschaft {
+    /// Identifier of the cohort
+    pub kohorte: String,
+    pub gruppe: Option<ModulGruppe>,
+    /// Whether the group was set with `dm set group`, otherwise it follows the Discord roles
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manuell: bool,
+}
 
-    pub fn disable(&mut self, user_id: UserId) -> Result<()> {
-        if let Some(v) = self
-            .users_config
-            .user
+/// Returns whether the dates of a module concern members of the given cohorts
+pub fn affects(kohorten: &[Mitgliedschaft], modul: &Modul) -> bool {
+    kohorten.iter().any(|mitgliedschaft| {
+        mitgliedschaft.kohorte == modul.typ.kohorte
+            && (modul.gruppe.is_none() || modul.gruppe == mitgliedschaft.gruppe)
+    })
+}
+
+/// Returns whether the dates of a module are shown by `list` and in the digest
+///
+/// Users without cohorts only see the dates that are not held in groups.
+pub fn is_visible(kohorten: &[Mitgliedschaft], abo: Option<&Abo>, modul: &Modul) -> bool {
+    let affected = if kohorten.is_empty() {
+        modul.gruppe.is_none()
+    } else {
+        affects(kohorten, modul)
+    };
+    affected && abo.is_none_or(|abo| abo.includes(modul))
+}
+
+/// Detects the cohorts and groups of a user from the Discord roles for which `has_role` returns
+/// `true`
+pub fn detect_kohorten<F: Fn(u64) -> bool>(
+    app_config: &crate::Config,
+    has_role: F,
+) -> Vec<Mitgliedschaft> {
+    app_config
+        .kohorten
+        .iter()
+        .filter(|kohorte| kohorte.is_member(&has_role))
+        .map(|kohorte| Mitgliedschaft {
+            kohorte: kohorte.id.clone(),
+            gruppe: kohorte.find_group(&has_role),
+            manuell: false,
+        })
+        .collect()
+}
+
+/// Returns the settings of a user that is not stored yet, the cohorts are detected from the roles
+/// of the user
+pub fn new_user(app_config: &crate::Config, user_id: UserId, roles: &[u64]) -> User {
+    User {
+        id: user_id,
+        gruppe: None,
+        enabled: false,
+        send_before: vec![Duration { minutes: 30 }],
+        send_after_previous: false,
+        digest: None,
+        ruhezeit: None,
+        pause_bis: None,
+        sprache: Sprache::default(),
+        zeitzone: None,
+        abo: Abo::default(),
+        kohorten: detect_kohorten(app_config, |role_id| roles.contains(&role_id)),
+    }
+}
+
+/// A backend that stores the settings of the users
+pub trait UserStore: Send {
+    /// Reloads the users from the backend to pick up changes made outside of the bot
+    fn refresh(&mut self) -> Result<()>;
+
+    fn get_all(&self) -> &[User];
+
+    /// Inserts a new user or replaces the stored user with the same id
+    fn put(&mut self, user: User) -> Result<()>;
+
+    fn delete(&mut self, user_id: UserId) -> Result<()>;
+
+    fn get(&self, user_id: UserId) -> Option<&User> {
+        self.get_all().iter().find(|v| v.id == user_id)
+    }
+
+    /// Applies `f` to the stored user, new users have to be added with [`new_user`] first
+    fn update(&mut self, user_id: UserId, f: &mut dyn FnMut(&mut User)) -> Result<()> {
+        let mut user = self
+            .get(user_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown user `{}`", user_id))?;
+        f(&mut user);
+        self.put(user)
+    }
+
+    fn enable(&mut self, user_id: UserId) -> Result<()> {
+        self.update(user_id, &mut |user| user.enabled = true)
+    }
+
+    fn disable(&mut self, user_id: UserId) -> Result<()> {
+        match self.get(user_id).cloned() {
+            Some(mut user) => {
+                user.enabled = false;
+                self.put(user)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn remove(&mut self, user_id: UserId) -> Result<()> {
+        self.delete(user_id)
+    }
+
+    fn set_send_before(&mut self, user_id: UserId, value: Vec<Duration>) -> Result<()> {
+        self.update(user_id, &mut |user| user.send_before = value.clone())
+    }
+
+    fn set_send_after(&mut self, user_id: UserId, value: bool) -> Result<()> {
+        self.update(user_id, &mut |user| user.send_after_previous = value)
+    }
+
+    fn set_digest(&mut self, user_id: UserId, value: Option<NaiveTime>) -> Result<()> {
+        self.update(user_id, &mut |user| user.digest = value)
+    }
+
+    fn set_ruhezeit(&mut self, user_id: UserId, value: Option<Ruhezeit>) -> Result<()> {
+        self.update(user_id, &mut |user| user.ruhezeit = value.clone())
+    }
+
+    fn set_pause(&mut self, user_id: UserId, value: Option<NaiveDate>) -> Result<()> {
+        self.update(user_id, &mut |user| user.pause_bis = value)
+    }
+
+    fn set_sprache(&mut self, user_id: UserId, value: Sprache) -> Result<()> {
+        self.update(user_id, &mut |user| user.sprache = value)
+    }
+
+    fn set_zeitzone(&mut self, user_id: UserId, value: Option<Tz>) -> Result<()> {
+        self.update(user_id, &mut |user| user.zeitzone = value)
+    }
+
+    fn set_abo(&mut self, user_id: UserId, value: Abo) -> Result<()> {
+        self.update(user_id, &mut |user| user.abo = value.clone())
+    }
+
+    fn set_kohorten(&mut self, user_id: UserId, value: Vec<String>) -> Result<()> {
+        self.update(user_id, &mut |user| {
+            let mut kohorten = Vec::new();
+            for kohorte in &value {
+                match user.kohorten.iter().position(|v| v.kohorte == *kohorte) {
+                    Some(i) => kohorten.push(user.kohorten.remove(i)),
+                    None => kohorten.push(Mitgliedschaft {
+                        kohorte: kohorte.clone(),
+                        gruppe: None,
+                        manuell: false,
+                    }),
+                }
+            }
+            user.kohorten = kohorten;
+        })
+    }
+
+    /// Sets the group of the user in a cohort, `manuell` is `false` if the group follows the
+    /// Discord roles again
+    fn set_group(
+        &mut self,
+        user_id: UserId,
+        kohorte: &str,
+        value: Option<ModulGruppe>,
+        manuell: bool,
+    ) -> Result<()> {
+        self.update(user_id, &mut |user| match user
+            .kohorten
             .iter_mut()
-            .find(|user| user.id == user_id)
+            .find(|v| v.kohorte == kohorte)
         {
-            v.enabled = false;
+            Some(mitgliedschaft) => {
+                mitgliedschaft.gruppe = value.clone();
+                mitgliedschaft.manuell = manuell;
+            }
+            None => user.kohorten.push(Mitgliedschaft {
+                kohorte: kohorte.to_owned(),
+                gruppe: value.clone(),
+                manuell,
+            }),
+        })
+    }
+
+    /// Updates the groups of a stored user after the Discord roles of the user changed and
+    /// returns whether they changed, users that are not stored are ignored
+    fn sync_roles(&mut self, user_id: UserId, detected: &[Mitgliedschaft]) -> Result<bool> {
+        let mut user = match self.get(user_id) {
+            Some(v) => v.clone(),
+            None => return Ok(false),
+        };
+        if !user.sync_kohorten(detected) {
+            return Ok(false);
         }
-        self.write()
+        self.put(user)?;
+        Ok(true)
     }
+}
 
-    pub fn remove(&mut self, user_id: UserId) -> Result<()> {
-        if let Some(i) = self
-            .users_config
-            .user
-            .iter()
-            .position(|user| user.id == user_id)
-        {
-            self.users_config.user.remove(i);
+/// Opens the backend selected in the `users` section of the config
+pub fn from_config(app_config: Arc<crate::Config>) -> Result<Box<dyn UserStore>> {
+    let path = app_config.resolve_path(&app_config.users.file);
+    Ok(match app_config.users.backend {
+        Backend::File => Box::new(file::FileStore::new(path, app_config)?),
+        Backend::Sqlite => Box::new(sqlite::SqliteStore::new(path)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modul(id: &str, gruppe: Option<&str>) -> Modul {
+        crate::fixture::modul(id, gruppe, Vec::new())
+    }
+
+    #[test]
+    fn legacy_send_before() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            #[serde(default, deserialize_with = "deserialize_send_before")]
+            send_before: Vec<Duration>,
         }
-        self.write()
+        let minutes = |input: &str| {
+            toml::from_str::<Wrapper>(input)
+                .unwrap()
+                .send_before
+                .into_iter()
+                .map(|v| v.minutes)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(minutes("send_before = 30"), vec![30]);
+        assert_eq!(minutes("send_before = [60, 10]"), vec![60, 10]);
+        assert_eq!(minutes(""), Vec::<u64>::new());
     }
 
-    pub fn set_send_before(&mut self, user_id: UserId, value: Option<Duration>) -> Result<()> {
-        let user = self.get_mut_or_add(user_id)?;
-        user.send_before = value;
-        self.write()
+    #[test]
+    fn ruhezeit() {
+        let time = |hour| NaiveTime::from_hms(hour, 0, 0);
+        let ruhezeit = "22:00-07:00".parse::<Ruhezeit>().unwrap();
+        assert_eq!(ruhezeit.to_string(), "22:00-07:00");
+        assert!(ruhezeit.contains(time(23)));
+        assert!(ruhezeit.contains(time(6)));
+        assert!(!ruhezeit.contains(time(7)));
+        assert!(!ruhezeit.contains(time(12)));
+
+        let ruhezeit = "12:00-14:00".parse::<Ruhezeit>().unwrap();
+        assert!(ruhezeit.contains(time(13)));
+        assert!(!ruhezeit.contains(time(14)));
+        assert!("22:00".parse::<Ruhezeit>().is_err());
     }
 
-    pub fn set_send_after(&mut self, user_id: UserId, value: bool) -> Result<()> {
-        let user = self.get_mut_or_add(user_id)?;
-        user.send_after_previous = value;
-        self.write()
+    #[test]
+    fn abo() {
+        let mut abo = Abo::default();
+        assert!(abo.includes(&modul("digitaltechnik", None)));
+        assert!(abo.includes(&modul("digitaltechnik", Some("Gruppe 1"))));
+
+        abo.stumm.push("ain1/digitaltechnik".to_owned());
+        abo.uebungen = false;
+        assert!(!abo.includes(&modul("digitaltechnik", None)));
+        assert!(abo.includes(&modul("mathematik1", None)));
+        assert!(!abo.includes(&modul("mathematik1", Some("Gruppe 1"))));
     }
 
-    pub fn set_group(&mut self, user_id: UserId, value: Option<ModulGruppe>) -> Result<()> {
-        let user = self.get_mut_or_add(user_id)?;
-        user.gruppe = value;
-        self.write()
+    #[test]
+    fn sync_kohorten() {
+        let mitgliedschaft = |kohorte: &str, gruppe: Option<&str>, manuell| Mitgliedschaft {
+            kohorte: kohorte.to_owned(),
+            gruppe: gruppe.map(ModulGruppe::new),
+            manuell,
+        };
+        let mut user: User =
+            toml::from_str("id = 1\nenabled = true\nsend_after_previous = false").unwrap();
+        user.kohorten = vec![
+            mitgliedschaft("ain1", Some("Gruppe 1"), false),
+            mitgliedschaft("win1", Some("Gruppe 1"), true),
+        ];
+        let detected = vec![
+            mitgliedschaft("ain1", Some("Gruppe 2"), false),
+            mitgliedschaft("win1", Some("Gruppe 2"), false),
+        ];
+        assert!(user.sync_kohorten(&detected));
+        assert_eq!(user.kohorten[0].gruppe, Some(ModulGruppe::new("Gruppe 2")));
+        assert_eq!(user.kohorten[1].gruppe, Some(ModulGruppe::new("Gruppe 1")));
+        assert!(!user.sync_kohorten(&detected));
+
+        assert!(user.sync_kohorten(&[]));
+        assert_eq!(user.kohorten[0].gruppe, None);
     }
 }
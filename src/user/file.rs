@@ -0,0 +1,196 @@
+use super::{Mitgliedschaft, User, UserStore};
+use crate::persist;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serenity::model::id::UserId;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Version of the users file, has to be increased on incompatible changes of [`User`]
+const VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize)]
+struct Config {
+    /// `0` for files that were written before cohorts existed
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
+    user: Vec<User>,
+}
+
+impl Config {
+    fn new(file_path: &Path, app_config: &crate::Config) -> Result<Self> {
+        let mut config = Self::parse(file_path)?;
+        config.migrate(app_config);
+        Ok(config)
+    }
+
+    /// Reads the file or, if it cannot be parsed, the most recent backup, which is only done at
+    /// startup so a manual edit in progress is never replaced by a backup
+    fn new_or_backup(file_path: &Path, app_config: &crate::Config) -> Result<Self> {
+        Self::new(file_path, app_config).or_else(|e| {
+            let mut config = Self::from_backups(file_path, app_config.users.backups).ok_or(e)?;
+            config.migrate(app_config);
+            Ok(config)
+        })
+    }
+
+    fn parse(file_path: &Path) -> Result<Self> {
+        Self::parse_str(&persist::read(file_path)?, file_path)
+    }
+
+    fn parse_str(string: &str, file_path: &Path) -> Result<Self> {
+        toml::from_str(string)
+            .with_context(|| format!("Failed to deserialize `{}`", file_path.display()))
+    }
+
+    /// Reads the most recent backup that can be parsed
+    fn from_backups(file_path: &Path, backups: usize) -> Option<Self> {
+        (1..=backups).find_map(|n| {
+            let backup_path = persist::backup_path(file_path, n);
+            let config = Self::parse(&backup_path).ok()?;
+            log::warn!(
+                "Failed to read `{}`, using backup `{}`",
+                file_path.display(),
+                backup_path.display()
+            );
+            Some(config)
+        })
+    }
+
+    /// Moves the group of every user in a file without cohorts into the first configured cohort
+    fn migrate(&mut self, app_config: &crate::Config) {
+        if self.version > 0 {
+            return;
+        }
+        if let Some(kohorte) = app_config.kohorten.first() {
+            for user in &mut self.user {
+                user.kohorten.push(Mitgliedschaft {
+                    kohorte: kohorte.id.clone(),
+                    gruppe: user.gruppe.take(),
+                    manuell: false,
+                });
+            }
+        }
+        self.version = VERSION;
+    }
+}
+
+/// Reads the users of a TOML file
+pub fn read(file_path: &Path, app_config: &crate::Config) -> Result<Vec<User>> {
+    Ok(Config::new(file_path, app_config)?.user)
+}
+
+/// Stores the users in a TOML file, which is re-read on every refresh so it can be edited by hand
+pub struct FileStore {
+    file_path: PathBuf,
+    users_config: Config,
+    app_config: Arc<crate::Config>,
+}
+
+impl FileStore {
+    pub fn new(file_path: PathBuf, app_config: Arc<crate::Config>) -> Result<Self> {
+        Ok(Self {
+            users_config: Config::new_or_backup(&file_path, &app_config)?,
+            file_path,
+            app_config,
+        })
+    }
+
+    /// Applies a change to the users in the file instead of the copy that was read last, so users
+    /// that were edited by hand in the meantime are kept
+    fn update(&mut self, f: impl FnOnce(&mut Vec<User>)) -> Result<()> {
+        let mut updated = None;
+        persist::update(&self.file_path, self.app_config.users.backups, |string| {
+            let mut config = Config::parse_str(&string, &self.file_path)?;
+            config.migrate(&self.app_config);
+            f(&mut config.user);
+            let string = toml::to_string_pretty(&config)?;
+            updated = Some(config);
+            Ok(string)
+        })?;
+        if let Some(config) = updated {
+            self.users_config = config;
+        }
+        Ok(())
+    }
+}
+
+impl UserStore for FileStore {
+    /// Keeps the users that were read last if the file cannot be parsed, e.g. while it is edited
+    fn refresh(&mut self) -> Result<()> {
+        self.users_config = Config::new(&self.file_path, &self.app_config)
+            .context("Keeping the previously read users")?;
+        Ok(())
+    }
+
+    fn get_all(&self) -> &[User] {
+        &self.users_config.user
+    }
+
+    fn put(&mut self, user: User) -> Result<()> {
+        self.update(|users| match users.iter_mut().find(|v| v.id == user.id) {
+            Some(v) => *v = user,
+            None => users.push(user),
+        })
+    }
+
+    fn delete(&mut self, user_id: UserId) -> Result<()> {
+        self.update(|users| users.retain(|v| v.id != user_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modul::ModulGruppe;
+
+    #[test]
+    fn migrate_legacy_file() {
+        let app_config: crate::Config = toml::from_str(
+            r#"
+            [discord]
+            bot_token = "token"
+            guild_id = 1
+
+            [users]
+            file = "users.toml"
+
+            [[kohorte]]
+            id = "ain1"
+            name = "AIN 1"
+            modul = []
+            "#,
+        )
+        .unwrap();
+        let mut config: Config = toml::from_str(
+            r#"
+            [[user]]
+            id = 1
+            gruppe = "Gruppe2"
+            enabled = true
+            send_after_previous = false
+
+            [[user]]
+            id = 2
+            enabled = false
+            send_after_previous = false
+            "#,
+        )
+        .unwrap();
+        config.migrate(&app_config);
+        assert_eq!(config.version, VERSION);
+        assert_eq!(config.user[0].kohorten[0].kohorte, "ain1");
+        assert_eq!(
+            config.user[0].kohorten[0].gruppe,
+            Some(ModulGruppe::new("Gruppe 2"))
+        );
+        assert_eq!(config.user[1].kohorten[0].gruppe, None);
+
+        let string = toml::to_string_pretty(&config).unwrap();
+        let mut config: Config = toml::from_str(&string).unwrap();
+        config.user[1].kohorten.clear();
+        config.migrate(&app_config);
+        assert!(config.user[1].kohorten.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use super::{User, UserStore};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use serenity::model::id::UserId;
+use std::path::{Path, PathBuf};
+
+/// Opens the database and creates the table if necessary
+///
+/// Every user is stored as JSON, so new settings do not need a schema migration.
+fn open(path: &Path) -> Result<Connection> {
+    let connection = Connection::open(path)
+        .with_context(|| format!("Failed to open database `{}`", path.display()))?;
+    connection
+        .execute(
+            "CREATE TABLE IF NOT EXISTS users (id INTEGER PRIMARY KEY, data TEXT NOT NULL)",
+            params![],
+        )
+        .context("Failed to create users table")?;
+    Ok(connection)
+}
+
+fn load(connection: &Connection) -> Result<Vec<User>> {
+    let mut statement = connection.prepare("SELECT data FROM users ORDER BY rowid")?;
+    let rows = statement.query_map(params![], |row| row.get::<_, String>(0))?;
+    rows.map(|data| serde_json::from_str(&data?).context("Failed to deserialize user"))
+        .collect()
+}
+
+fn put(connection: &Connection, user: &User) -> Result<()> {
+    let data = serde_json::to_string(user).context("Failed to serialize user")?;
+    connection
+        .execute(
+            "INSERT INTO users (id, data) VALUES (?1, ?2) \
+             ON CONFLICT(id) DO UPDATE SET data = excluded.data",
+            params![user.id.0 as i64, data],
+        )
+        .context("Failed to store user")?;
+    Ok(())
+}
+
+fn delete(connection: &Connection, user_id: UserId) -> Result<()> {
+    connection
+        .execute("DELETE FROM users WHERE id = ?1", params![user_id.0 as i64])
+        .context("Failed to delete user")?;
+    Ok(())
+}
+
+/// Imports users into the database, replacing stored users with the same id
+pub fn import(path: &Path, users: &[User]) -> Result<()> {
+    let mut connection = open(path)?;
+    let transaction = connection.transaction()?;
+    for user in users {
+        put(&transaction, user)?;
+    }
+    transaction.commit().context("Failed to import users")
+}
+
+/// Stores the users in an embedded SQLite database
+pub struct SqliteStore {
+    connection: Connection,
+    users: Vec<User>,
+}
+
+impl SqliteStore {
+    pub fn new(path: PathBuf) -> Result<Self> {
+        let connection = open(&path)?;
+        Ok(Self {
+            users: load(&connection)?,
+            connection,
+        })
+    }
+}
+
+impl UserStore for SqliteStore {
+    fn refresh(&mut self) -> Result<()> {
+        self.users = load(&self.connection)?;
+        Ok(())
+    }
+
+    fn get_all(&self) -> &[User] {
+        &self.users
+    }
+
+    fn put(&mut self, user: User) -> Result<()> {
+        put(&self.connection, &user)?;
+        match self.users.iter_mut().find(|v| v.id == user.id) {
+            Some(v) => *v = user,
+            None => self.users.push(user),
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, user_id: UserId) -> Result<()> {
+        delete(&self.connection, user_id)?;
+        self.users.retain(|v| v.id != user_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::{Abo, Duration, Mitgliedschaft};
+
+    fn user(id: u64, enabled: bool) -> User {
+        User {
+            id: UserId(id),
+            gruppe: None,
+            enabled,
+            send_before: vec![Duration { minutes: 30 }],
+            send_after_previous: false,
+            digest: None,
+            ruhezeit: None,
+            pause_bis: None,
+            sprache: Default::default(),
+            zeitzone: None,
+            abo: Abo::default(),
+            kohorten: vec![Mitgliedschaft {
+                kohorte: "ain1".to_owned(),
+                gruppe: None,
+                manuell: false,
+            }],
+        }
+    }
+
+    #[test]
+    fn put_and_delete() {
+        let connection = open(Path::new(":memory:")).unwrap();
+        put(&connection, &user(1, false)).unwrap();
+        put(&connection, &user(2, false)).unwrap();
+        put(&connection, &user(1, true)).unwrap();
+        let users = load(&connection).unwrap();
+        assert_eq!(users.len(), 2);
+        assert!(users[0].enabled);
+        assert_eq!(users[0].kohorten[0].kohorte, "ain1");
+
+        delete(&connection, UserId(1)).unwrap();
+        let users = load(&connection).unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, UserId(2));
+    }
+}
@@ -0,0 +1,272 @@
+use crate::persist;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Name of the delivery log, which is stored next to the users file
+pub const FILE_NAME: &str = "deliveries.json";
+
+/// Version of the on-disk format
+const VERSION: u32 = 1;
+
+/// Identifies a reminder that was delivered
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub struct Zustellung {
+    /// Receiver of the reminder (e.g. `channel/123` or `user/456`)
+    pub ziel: String,
+    /// Date the reminder belongs to, or the day of a digest or preview
+    pub termin: String,
+    /// Minutes before the date or kind of the reminder (e.g. `30`, `after` or `digest`)
+    pub offset: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Eintrag {
+    #[serde(flatten)]
+    zustellung: Zustellung,
+    /// Time after which the entry is no longer needed
+    ende: DateTime<Utc>,
+}
+
+/// Reminder message in a group channel that is cleaned up after its date
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Nachricht {
+    pub channel_id: u64,
+    pub message_id: u64,
+    /// Content of the message as it was sent
+    pub inhalt: String,
+    pub beginn: DateTime<Utc>,
+    pub ende: DateTime<Utc>,
+    /// Cancelled dates are never marked as running
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ausfall: bool,
+    /// Whether the message has been marked as running
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub laeuft: bool,
+}
+
+impl Nachricht {
+    /// Returns the time of the next change, `mark_running` includes the beginning of the date
+    fn aenderung(&self, mark_running: bool) -> DateTime<Utc> {
+        if mark_running && !self.laeuft && !self.ausfall {
+            self.beginn
+        } else {
+            self.ende
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Datei {
+    version: u32,
+    bis: Option<DateTime<Utc>>,
+    eintraege: Vec<Eintrag>,
+    #[serde(default)]
+    nachrichten: Vec<Nachricht>,
+}
+
+/// Durable log of the delivered reminders, so restarts never send a reminder twice
+///
+/// Changes are kept in memory until [`Protokoll::save`] is called.
+pub struct Protokoll {
+    path: PathBuf,
+    bis: Option<DateTime<Utc>>,
+    eintraege: HashMap<Zustellung, DateTime<Utc>>,
+    nachrichten: Vec<Nachricht>,
+    /// Whether there are entries that have not been saved yet
+    geaendert: bool,
+}
+
+impl Protokoll {
+    /// Reads the log, which is empty if the file does not exist yet
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let mut protokoll = Self::empty(path);
+        let string = match fs::read_to_string(&protokoll.path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(protokoll),
+            Err(e) => return Err(e).context("Failed to read delivery log"),
+        };
+        let datei: Datei =
+            serde_json::from_str(&string).context("Failed to deserialize delivery log")?;
+        if datei.version != VERSION {
+            return Err(anyhow!(
+                "Unsupported delivery log version `{}` (expected `{}`)",
+                datei.version,
+                VERSION
+            ));
+        }
+        protokoll.bis = datei.bis;
+        protokoll.eintraege = datei
+            .eintraege
+            .into_iter()
+            .map(|v| (v.zustellung, v.ende))
+            .collect();
+        protokoll.nachrichten = datei.nachrichten;
+        Ok(protokoll)
+    }
+
+    /// Returns an empty log, e.g. if the file could not be read
+    pub fn empty(path: PathBuf) -> Self {
+        Self {
+            path,
+            bis: None,
+            eintraege: HashMap::new(),
+            nachrichten: Vec::new(),
+            geaendert: false,
+        }
+    }
+
+    /// Returns the time up to which all reminders have been handled
+    pub fn bis(&self) -> Option<DateTime<Utc>> {
+        self.bis
+    }
+
+    pub fn contains(&self, zustellung: &Zustellung) -> bool {
+        self.eintraege.contains_key(zustellung)
+    }
+
+    /// Records a delivered reminder, which is kept until `ende`
+    pub fn insert(&mut self, zustellung: Zustellung, ende: DateTime<Utc>) {
+        self.eintraege.insert(zustellung, ende);
+        self.geaendert = true;
+    }
+
+    /// Marks all reminders up to `bis` as handled and removes the entries that ended before
+    ///
+    /// The time alone does not need to be saved, as an older time only causes reminders to be
+    /// checked against the log again after a restart.
+    pub fn advance(&mut self, bis: DateTime<Utc>) {
+        self.bis = Some(bis);
+        let len = self.eintraege.len();
+        self.eintraege.retain(|_, ende| *ende >= bis);
+        self.geaendert |= self.eintraege.len() != len;
+    }
+
+    /// Records a reminder message that is cleaned up after its date
+    pub fn insert_nachricht(&mut self, nachricht: Nachricht) {
+        self.nachrichten.push(nachricht);
+        self.geaendert = true;
+    }
+
+    /// Returns the time of the next change of a recorded message
+    pub fn next_nachricht(&self, mark_running: bool) -> Option<DateTime<Utc>> {
+        self.nachrichten
+            .iter()
+            .map(|v| v.aenderung(mark_running))
+            .min()
+    }
+
+    /// Returns the messages that change until `now`
+    ///
+    /// Messages whose date has ended are removed, the others are marked as running.
+    pub fn pop_nachrichten(&mut self, now: DateTime<Utc>, mark_running: bool) -> Vec<Nachricht> {
+        let mut changed = Vec::new();
+        self.nachrichten.retain_mut(|nachricht| {
+            if nachricht.aenderung(mark_running) > now {
+                return true;
+            }
+            nachricht.laeuft = nachricht.ende > now;
+            changed.push(nachricht.clone());
+            nachricht.laeuft
+        });
+        self.geaendert |= !changed.is_empty();
+        changed
+    }
+
+    /// Writes the log if it changed since it was last saved
+    pub fn save(&mut self) -> Result<()> {
+        if !self.geaendert {
+            return Ok(());
+        }
+        let mut eintraege = self
+            .eintraege
+            .iter()
+            .map(|(zustellung, ende)| Eintrag {
+                zustellung: zustellung.clone(),
+                ende: *ende,
+            })
+            .collect::<Vec<_>>();
+        eintraege.sort_by_key(|v| v.ende);
+        let datei = Datei {
+            version: VERSION,
+            bis: self.bis,
+            eintraege,
+            nachrichten: self.nachrichten.clone(),
+        };
+        let string =
+            serde_json::to_string_pretty(&datei).context("Failed to serialize delivery log")?;
+        persist::write(&self.path, &string, 0).context("Failed to write delivery log")?;
+        self.geaendert = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::temp_dir;
+    use chrono::TimeZone;
+
+    #[test]
+    fn persist_and_prune() {
+        let path = temp_dir("deliveries").join(FILE_NAME);
+        let zustellung = |offset: &str| Zustellung {
+            ziel: "user/1".to_owned(),
+            termin: "ain1/digitaltechnik//2020-10-05T06:00:00+00:00".to_owned(),
+            offset: offset.to_owned(),
+        };
+        let time = |hour| Utc.ymd(2020, 10, 5).and_hms(hour, 0, 0);
+
+        let mut protokoll = Protokoll::load(path.clone()).unwrap();
+        assert_eq!(protokoll.bis(), None);
+        protokoll.insert(zustellung("60"), time(7));
+        protokoll.insert(zustellung("10"), time(9));
+        protokoll.advance(time(8));
+        protokoll.save().unwrap();
+
+        let protokoll = Protokoll::load(path).unwrap();
+        assert_eq!(protokoll.bis(), Some(time(8)));
+        assert!(!protokoll.contains(&zustellung("60")));
+        assert!(protokoll.contains(&zustellung("10")));
+    }
+
+    #[test]
+    fn nachrichten() {
+        let path = temp_dir("messages").join(FILE_NAME);
+        let time = |hour| Utc.ymd(2020, 10, 5).and_hms(hour, 0, 0);
+
+        let mut protokoll = Protokoll::load(path.clone()).unwrap();
+        let nachricht = Nachricht {
+            channel_id: 11,
+            message_id: 31,
+            inhalt: "<@&21>".to_owned(),
+            beginn: time(6),
+            ende: time(8),
+            ausfall: false,
+            laeuft: false,
+        };
+        protokoll.insert_nachricht(nachricht.clone());
+        assert_eq!(protokoll.next_nachricht(true), Some(time(6)));
+        assert_eq!(protokoll.next_nachricht(false), Some(time(8)));
+        assert!(protokoll.pop_nachrichten(time(5), true).is_empty());
+        let changed = protokoll.pop_nachrichten(time(6), true);
+        assert_eq!(
+            changed,
+            vec![Nachricht {
+                laeuft: true,
+                ..nachricht.clone()
+            }]
+        );
+        protokoll.save().unwrap();
+
+        let mut protokoll = Protokoll::load(path).unwrap();
+        assert_eq!(protokoll.next_nachricht(true), Some(time(8)));
+        let changed = protokoll.pop_nachrichten(time(9), true);
+        assert_eq!(changed, vec![nachricht]);
+        assert_eq!(protokoll.next_nachricht(true), None);
+    }
+}